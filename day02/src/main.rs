@@ -1,109 +1,107 @@
 use std::env;
-use std::fs;
 use std::process;
 use std::time::Instant;
+pub mod intcode;
+mod symbolic;
 
-fn read_data(filename: &str) -> Vec<usize> {
-	fs::read_to_string(filename)
-		.expect("Expected input file to exist")
-		.replace("\n", "")
-		.split(',')
-		.map(|line| line.parse::<usize>().unwrap())
-		.collect()
-}
-
-fn add(memory: &mut Vec<usize>, pc : usize) -> usize {
-	let p1 = memory[pc+1];
-	let p2 = memory[pc+2];
-	let p3 = memory[pc+3];
-	// println!("add {op1} * {op2} => {dst}");
-	memory[p3] = memory[p1] + memory[p2];
-	return pc + 4;
-}
-
-fn mul(memory: &mut Vec<usize>, pc : usize) -> usize {
-	let p1 = memory[pc+1];
-	let p2 = memory[pc+2];
-	let p3 = memory[pc+3];
-	// println!("mul {op1} * {op2} => {dst}");
-	memory[p3] = memory[p1] * memory[p2];
-	return pc + 4;
-}
+use intcode::CpuError;
 
-fn term(memory: &mut Vec<usize>, _pc: usize) -> usize {
-	// println!("term at {pc}");
-	return memory.len();
+// command line options that follow the input file
+struct Options {
+	target: i64,			// value part 2 looks for in memory[0]
+	range: (i64, i64),		// noun and verb search range (inclusive)
+	show: bool,				// print final memory of part 1
 }
 
-fn show(memory: &Vec<usize>, pc: usize) {
-	for (i, v) in memory.iter().enumerate() {
-		if i == pc {
-			print!("({v})");
-		} else {
-			print!("{v}");
-		}
-		if i < memory.len()-1 {
-			print!(",");
+fn read_options(args: &[String]) -> Result<Options, String> {
+	let mut options = Options { target: 19690720, range: (0, 99), show: false };
+	let mut args = args.iter();
+
+	while let Some(arg) = args.next() {
+		match arg.as_str() {
+			"--show" => options.show = true,
+			"--target" => {
+				let value = args.next().ok_or("--target needs a value")?;
+				options.target = value.parse().map_err(|_| format!("Bad target {value}"))?;
+			},
+			"--range" => {
+				// LO..HI, both ends included
+				let value = args.next().ok_or("--range needs LO..HI")?;
+				let (lo, hi) = value.split_once("..").ok_or(format!("Bad range {value}"))?;
+				let lo = lo.parse().map_err(|_| format!("Bad range {value}"))?;
+				let hi = hi.parse().map_err(|_| format!("Bad range {value}"))?;
+				if lo > hi {
+					return Err(format!("Empty range {value}"));
+				}
+				options.range = (lo, hi);
+			},
+			_ => return Err(format!("Unknown option {arg}")),
 		}
 	}
-	println!("")
+
+	return Ok(options);
 }
 
-fn run_program(memory: &mut Vec<usize>, start: usize) -> usize {
-	let mut pc: usize = start;
+fn read_data(filename: &str) -> Vec<i64> {
+	return intcode::read_program(filename);
+}
 
-	while pc < memory.len() {
-		// show(&memory, pc);
-		match memory[pc] {
-			1	=> pc = add(memory, pc),
-			2	=> pc = mul(memory, pc),
-			99 	=> pc = term(memory, pc),
-			n 	=> println!("{pc}: unknown operation {n}")
-		}
-	}
+// run program with noun and verb patched in, returns memory[0]
+fn run_program(program: &Vec<i64>, noun: i64, verb: i64) -> Result<i64, CpuError> {
+	let mut cpu = intcode::CPU::load_program(program);
+	cpu.set_memory(1, noun)?;
+	cpu.set_memory(2, verb)?;
+	cpu.execute()?;
 
-	// show(&memory, pc);
-	return memory[0];
+	return Ok(cpu.get_memory(0));
 }
 
-fn part1(program: &Vec<usize>) -> usize {
-	let mut memory = program.clone();	
+fn part1(program: &Vec<i64>, options: &Options) -> Result<i64, CpuError> {
+	let mut cpu = intcode::CPU::load_program(program);
 
-	if program.len() < 20 {
-		// test input
-		run_program(&mut memory, 0);
-	} else {
+	if program.len() >= 20 {
 		// live input
-		memory[1] = 12;
-		memory[2] = 2;
-		run_program(&mut memory, 0);
+		cpu.set_memory(1, 12)?;
+		cpu.set_memory(2, 2)?;
+	}
+
+	cpu.execute()?;
+
+	if options.show {
+		cpu.show();
 	}
 
-	return memory[0];
+	return Ok(cpu.get_memory(0));
 }
 
-fn part2(program: &Vec<usize>) -> usize {
+fn part2(program: &Vec<i64>, options: &Options) -> Result<i64, String> {
+	let (lo, hi) = options.range;
+	let not_found = || format!("no noun and verb in {lo}..{hi} give {}", options.target);
 
 	if program.len() < 20 {
 		// test input
-		return 0;
+		return Ok(0);
 	}
 
-	// live input
-	for n in 0..=99 {
-		for v in 0..=99 {
-			let mut memory = program.clone();	
-			memory[1] = n;
-			memory[2] = v;
-			run_program(&mut memory, 0);
+	// solve directly when memory[0] is linear in noun and verb
+	let unknowns = [1, 2];
+	if let Some(linear) = symbolic::execute(program, &unknowns).ok().and_then(|m| m[0].linear()) {
+		return match linear.solve(&unknowns, options.range, options.target) {
+			Some(nv) => Ok(100*nv[0] + nv[1]),
+			None => Err(not_found()),
+		};
+	}
 
-			if memory[0] == 19690720 {
-				return 100*n + v;
+	// otherwise search all noun/verb pairs, skipping ones that fault
+	for n in lo..=hi {
+		for v in lo..=hi {
+			if run_program(program, n, v) == Ok(options.target) {
+				return Ok(100*n + v);
 			}
 		}
 	}
 
-	return 0;
+	return Err(not_found());
 }
 
 fn main() {
@@ -113,6 +111,11 @@ fn main() {
 		process::exit(1);
 	}
 
+	let options = read_options(&args[2..]).unwrap_or_else(|error| {
+		println!("ERROR: {error}");
+		process::exit(1);
+	});
+
 	let start = Instant::now();
 
 	let data = read_data(&args[1]);
@@ -122,14 +125,20 @@ fn main() {
 	);
 
 	let p1_start = Instant::now();
-	let p1 = part1(&data);
+	let p1 = part1(&data, &options).unwrap_or_else(|error| {
+		println!("ERROR: {error}");
+		process::exit(1);
+	});
 	println!(
 		"{p1:>15} ({:9.9} ns)",
 		Instant::now().duration_since(p1_start).as_nanos()
 	);
 
 	let p2_start = Instant::now();
-	let p2 = part2(&data);
+	let p2 = part2(&data, &options).unwrap_or_else(|error| {
+		println!("ERROR: {error}");
+		process::exit(1);
+	});
 
 	println!(
 		"{p2:>15} ({:9.9} ns)",
@@ -140,3 +149,78 @@ fn main() {
 		Instant::now().duration_since(start).as_nanos()
 	);
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	// a puzzle input, memory[0] is linear in the noun and verb
+	const INPUT: [i64; 165] = [
+		1, 0, 0, 3, 1, 1, 2, 3, 1, 3, 4, 3, 1, 5, 0, 3, 2, 1, 10, 19, 1, 19, 5, 23, 2, 23, 9,
+		27, 1, 5, 27, 31, 1, 9, 31, 35, 1, 35, 10, 39, 2, 13, 39, 43, 1, 43, 9, 47, 1, 47, 9,
+		51, 1, 6, 51, 55, 1, 13, 55, 59, 1, 59, 13, 63, 1, 13, 63, 67, 1, 6, 67, 71, 1, 71, 13,
+		75, 2, 10, 75, 79, 1, 13, 79, 83, 1, 83, 10, 87, 2, 9, 87, 91, 1, 6, 91, 95, 1, 9, 95,
+		99, 2, 99, 10, 103, 1, 103, 5, 107, 2, 6, 107, 111, 1, 111, 6, 115, 1, 9, 115, 119, 1,
+		9, 119, 123, 2, 10, 123, 127, 1, 127, 5, 131, 2, 6, 131, 135, 1, 135, 5, 139, 1, 9,
+		139, 143, 2, 143, 13, 147, 1, 9, 147, 151, 1, 151, 2, 155, 1, 9, 155, 0, 99, 2, 0, 14,
+		0,
+	];
+
+	fn options(target: i64, range: (i64, i64)) -> Options {
+		return Options { target, range, show: false };
+	}
+
+	// the first noun and verb a search finds, as part 2 answers
+	fn brute_force(program: &Vec<i64>, target: i64, (lo, hi): (i64, i64)) -> Option<i64> {
+		for n in lo..=hi {
+			for v in lo..=hi {
+				if run_program(program, n, v) == Ok(target) {
+					return Some(100*n + v);
+				}
+			}
+		}
+		return None;
+	}
+
+	#[test]
+	fn puzzle_answers() {
+		let program = INPUT.to_vec();
+		assert_eq!(part1(&program, &options(0, (0, 99))), Ok(3101878));
+		assert_eq!(part2(&program, &options(19690720, (0, 99))), Ok(8444));
+	}
+
+	#[test]
+	fn solving_agrees_with_searching() {
+		let program = INPUT.to_vec();
+		assert!(symbolic::execute(&program, &[1, 2]).unwrap()[0].linear().is_some());
+
+		for (noun, verb) in [(0, 0), (12, 2), (31, 77), (99, 99), (50, 0)] {
+			let target = run_program(&program, noun, verb).unwrap();
+			let expected = brute_force(&program, target, (0, 99)).unwrap();
+			assert_eq!(part2(&program, &options(target, (0, 99))), Ok(expected));
+		}
+	}
+
+	#[test]
+	fn falls_back_to_searching() {
+		// m0 = m[noun] * m[verb] reads through the noun and verb
+		let mut program = vec![2, 0, 0, 0, 99];
+		program.extend(5..25);
+		assert!(symbolic::execute(&program, &[1, 2]).unwrap()[0].linear().is_none());
+
+		let target = 7 * 11;
+		assert_eq!(part2(&program, &options(target, (0, 24))), Ok(brute_force(&program, target, (0, 24)).unwrap()));
+		assert_eq!(run_program(&program, 7, 11), Ok(target));
+	}
+
+	#[test]
+	fn no_answer_in_range() {
+		let program = INPUT.to_vec();
+		let error = Err("no noun and verb in 0..10 give 19690720".to_string());
+		assert_eq!(part2(&program, &options(19690720, (0, 10))), error);
+
+		let mut program = vec![2, 0, 0, 0, 99];
+		program.extend(5..25);
+		assert_eq!(part2(&program, &options(1, (5, 24))), Err("no noun and verb in 5..24 give 1".to_string()));
+	}
+}
@@ -0,0 +1,266 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+// Symbolic execution of straight-line add/mul programs
+//
+// Chosen memory cells are treated as unknowns and the program is executed
+// over expressions instead of numbers. If the target cell ends up linear in
+// the unknowns we can solve for them directly rather than running the
+// program for every combination.
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+	Const(i64),
+	Var(usize),					// unknown initial value of a memory cell
+	Load(Box<Expr>),			// read from an address that depends on an unknown
+	Add(Box<Expr>, Box<Expr>),
+	Mul(Box<Expr>, Box<Expr>),
+}
+
+// Why a program could not be executed symbolically
+#[derive(Clone, Debug, PartialEq)]
+pub enum Unsupported {
+	UnknownOp(usize, i64),		// pc, opcode (jumps, I/O, ...)
+	SymbolicCode(usize),		// instruction at pc depends on an unknown
+	SymbolicAddress(usize),		// write address of instruction at pc depends on an unknown
+	BadAddress(usize, i64),		// pc, address outside of memory
+	Overflow(usize),			// constant folding overflowed at pc
+}
+
+impl fmt::Display for Expr {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		return match self {
+			Expr::Const(n) => write!(f, "{n}"),
+			Expr::Var(address) => write!(f, "m{address}"),
+			Expr::Load(address) => write!(f, "m[{address}]"),
+			Expr::Add(a, b) => write!(f, "({a} + {b})"),
+			Expr::Mul(a, b) => write!(f, "{a} * {b}"),
+		};
+	}
+}
+
+impl Expr {
+	// build a + b, folding constants
+	fn add(a: &Expr, b: &Expr) -> Option<Expr> {
+		return Some(match (a, b) {
+			(Expr::Const(x), Expr::Const(y)) => Expr::Const(x.checked_add(*y)?),
+			(Expr::Const(0), e) | (e, Expr::Const(0)) => e.clone(),
+			(a, b) => Expr::Add(Box::new(a.clone()), Box::new(b.clone())),
+		});
+	}
+
+	// build a * b, folding constants
+	fn mul(a: &Expr, b: &Expr) -> Option<Expr> {
+		return Some(match (a, b) {
+			(Expr::Const(x), Expr::Const(y)) => Expr::Const(x.checked_mul(*y)?),
+			(Expr::Const(0), _) | (_, Expr::Const(0)) => Expr::Const(0),
+			(Expr::Const(1), e) | (e, Expr::Const(1)) => e.clone(),
+			(a, b) => Expr::Mul(Box::new(a.clone()), Box::new(b.clone())),
+		});
+	}
+
+	fn constant(&self) -> Option<i64> {
+		return match self {
+			Expr::Const(n) => Some(*n),
+			_ => None,
+		};
+	}
+
+	// expression as constant + sum(coefficient * unknown), None if not linear
+	pub fn linear(&self) -> Option<Linear> {
+		return match self {
+			Expr::Const(n) => Some(Linear { constant: *n, terms: BTreeMap::new() }),
+			Expr::Var(address) => Some(Linear { constant: 0, terms: BTreeMap::from([(*address, 1)]) }),
+			Expr::Load(_) => None,
+			Expr::Add(a, b) => a.linear()?.plus(&b.linear()?),
+			Expr::Mul(a, b) => {
+				let (a, b) = (a.linear()?, b.linear()?);
+				if a.terms.is_empty() {
+					b.scale(a.constant)
+				} else if b.terms.is_empty() {
+					a.scale(b.constant)
+				} else {
+					None
+				}
+			},
+		};
+	}
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Linear {
+	pub constant: i64,
+	pub terms: BTreeMap<usize, i64>,	// unknown's address -> coefficient
+}
+
+impl Linear {
+	fn plus(&self, other: &Linear) -> Option<Linear> {
+		let mut sum = self.clone();
+		sum.constant = sum.constant.checked_add(other.constant)?;
+
+		for (address, coefficient) in &other.terms {
+			let term = sum.terms.entry(*address).or_insert(0);
+			*term = term.checked_add(*coefficient)?;
+		}
+
+		return Some(sum);
+	}
+
+	fn scale(&self, factor: i64) -> Option<Linear> {
+		let mut terms = BTreeMap::new();
+		for (address, coefficient) in &self.terms {
+			terms.insert(*address, coefficient.checked_mul(factor)?);
+		}
+
+		return Some(Linear { constant: self.constant.checked_mul(factor)?, terms });
+	}
+
+	fn coefficient(&self, address: usize) -> i64 {
+		return *self.terms.get(&address).unwrap_or(&0);
+	}
+
+	// find values for unknowns (each within range) that make the expression
+	// equal target. The last unknown is solved for directly, the others are
+	// enumerated in order so the first solution matches a brute-force search.
+	pub fn solve(&self, unknowns: &[usize], range: (i64, i64), target: i64) -> Option<Vec<i64>> {
+		let (last, rest) = unknowns.split_last()?;
+		let mut values = vec![range.0; rest.len()];
+
+		loop {
+			// remaining = target - constant - sum of the enumerated terms
+			let mut remaining = target.checked_sub(self.constant);
+			for (address, value) in rest.iter().zip(&values) {
+				remaining = remaining?.checked_sub(self.coefficient(*address).checked_mul(*value)?);
+			}
+
+			if let Some(remaining) = remaining {
+				let coefficient = self.coefficient(*last);
+				let value = if coefficient == 0 {
+					if remaining == 0 { Some(range.0) } else { None }
+				} else if remaining % coefficient == 0 {
+					Some(remaining / coefficient)
+				} else {
+					None
+				};
+
+				if let Some(value) = value.filter(|v| range.0 <= *v && *v <= range.1) {
+					let mut solution = values.clone();
+					solution.push(value);
+					return Some(solution);
+				}
+			}
+
+			// advance the enumerated unknowns like an odometer
+			let mut i = values.len();
+			loop {
+				if i == 0 {
+					return None;
+				}
+				i -= 1;
+
+				if values[i] < range.1 {
+					values[i] += 1;
+					break;
+				}
+				values[i] = range.0;
+			}
+		}
+	}
+}
+
+// Run a straight-line add/mul program with the cells at `unknowns` treated
+// as variables. Returns the final memory as expressions.
+pub fn execute(program: &[i64], unknowns: &[usize]) -> Result<Vec<Expr>, Unsupported> {
+	let mut memory: Vec<Expr> = program.iter()
+		.enumerate()
+		.map(|(address, &value)| {
+			if unknowns.contains(&address) { Expr::Var(address) } else { Expr::Const(value) }
+		})
+		.collect();
+
+	let mut pc = 0;
+	while pc < memory.len() {
+		let op = memory[pc].constant().ok_or(Unsupported::SymbolicCode(pc))?;
+
+		match op {
+			1 | 2 => {
+				// all three operands are addresses, reads from an unknown
+				// address give an opaque value but writes must be known
+				let mut operand = Vec::new();
+				for i in 1..=3 {
+					let value = memory.get(pc + i).ok_or(Unsupported::BadAddress(pc, (pc + i) as i64))?;
+					operand.push(value.clone());
+				}
+
+				let load = |address: &Expr| {
+					return match address.constant() {
+						Some(a) => usize::try_from(a)
+							.ok()
+							.and_then(|a| memory.get(a).cloned())
+							.ok_or(Unsupported::BadAddress(pc, a)),
+						None => Ok(Expr::Load(Box::new(address.clone()))),
+					};
+				};
+
+				let (a, b) = (load(&operand[0])?, load(&operand[1])?);
+				let target = operand[2].constant().ok_or(Unsupported::SymbolicAddress(pc))?;
+				let target = usize::try_from(target)
+					.ok()
+					.filter(|a| *a < memory.len())
+					.ok_or(Unsupported::BadAddress(pc, target))?;
+
+				let value = if op == 1 { Expr::add(&a, &b) } else { Expr::mul(&a, &b) };
+				memory[target] = value.ok_or(Unsupported::Overflow(pc))?;
+				pc += 4;
+			},
+			99 => break,
+			n => return Err(Unsupported::UnknownOp(pc, n)),
+		}
+	}
+
+	return Ok(memory);
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	// m0 = 3 * m9 + m10
+	const LINEAR: [i64; 12] = [2, 9, 11, 0, 1, 0, 10, 0, 99, 0, 0, 3];
+
+	#[test]
+	fn solves_a_linear_program() {
+		let memory = execute(&LINEAR, &[9, 10]).unwrap();
+		let linear = memory[0].linear().unwrap();
+		assert_eq!(linear, Linear { constant: 0, terms: BTreeMap::from([(9, 3), (10, 1)]) });
+
+		// the first solution in the order a search would try them
+		assert_eq!(linear.solve(&[9, 10], (0, 99), 100), Some(vec![1, 97]));
+		assert_eq!(linear.solve(&[9, 10], (0, 99), 0), Some(vec![0, 0]));
+		assert_eq!(linear.solve(&[9, 10], (0, 99), 396), Some(vec![99, 99]));
+	}
+
+	#[test]
+	fn no_solution_in_range() {
+		let linear = execute(&LINEAR, &[9, 10]).unwrap()[0].linear().unwrap();
+		assert_eq!(linear.solve(&[9, 10], (0, 99), 397), None);
+		assert_eq!(linear.solve(&[9, 10], (0, 99), -1), None);
+		assert_eq!(linear.solve(&[9, 10], (0, 5), 100), None);
+	}
+
+	#[test]
+	fn not_linear() {
+		// m0 = m9 * m10
+		let memory = execute(&[2, 9, 10, 0, 99, 0, 0, 0, 0, 5, 6], &[9, 10]).unwrap();
+		assert_eq!(memory[0].to_string(), "m9 * m10");
+		assert_eq!(memory[0].linear(), None);
+
+		// reading through an unknown address
+		let memory = execute(&[1, 0, 0, 0, 99], &[1, 2]).unwrap();
+		assert_eq!(memory[0].to_string(), "(m[m1] + m[m2])");
+		assert_eq!(memory[0].linear(), None);
+
+		assert_eq!(execute(&[1101, 1, 2, 0, 99], &[1, 2]), Err(Unsupported::UnknownOp(0, 1101)));
+		assert_eq!(execute(&[1, 0, 0, 3, 99], &[3]), Err(Unsupported::SymbolicAddress(0)));
+	}
+}
@@ -0,0 +1,336 @@
+// Fuzzing harness for the Intcode CPUs
+//
+// Generates random and mutated programs, runs them under a step and memory
+// budget and checks that neither the day 5 CPU nor the shared intcode CPU
+// panics. Programs that stay inside what both CPUs support (opcodes 1-8 and
+// 99, position and immediate modes, memory within the program) are run on
+// both and must end the same way, halted or faulted, with the same output.
+// Write parameters get random modes too, an immediate write is a fault on
+// both.
+use std::panic;
+use crate::intcode;
+
+const STEP_BUDGET: usize = 10_000;		// instructions per run
+const MEMORY_BUDGET: usize = 1 << 16;	// memory cells per run
+const MAX_REPORTED: usize = 5;			// failures printed in detail
+
+// values likely to land on edge cases when used as operands
+const INTERESTING: [i64; 16] = [
+	0, 1, -1, 2, 3, 4, 8, 99, 100, 1000,
+	-1000, 1 << 31, i64::MAX, i64::MIN, i64::MAX / 2, i64::MIN + 1,
+];
+
+// day 5 example programs, used as seeds for mutation
+const SEEDS: [&[i64]; 9] = [
+	&[1002, 4, 3, 4, 33],
+	&[1101, 100, -1, 4, 0],
+	&[3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8],
+	&[3, 9, 7, 9, 10, 9, 4, 9, 99, -1, 8],
+	&[3, 3, 1108, -1, 8, 3, 4, 3, 99],
+	&[3, 3, 1107, -1, 8, 3, 4, 3, 99],
+	&[3, 12, 6, 12, 15, 1, 13, 14, 13, 4, 13, 99, -1, 0, 1, 9],
+	&[3, 3, 1105, -1, 9, 1101, 0, 0, 12, 4, 12, 99, 1],
+	&[3, 21, 1008, 21, 8, 20, 1005, 20, 22, 107, 8, 21, 20, 1006, 20, 31,
+	  1106, 0, 36, 98, 0, 0, 1002, 21, 125, 20, 4, 20, 1105, 1, 46, 104,
+	  999, 1105, 1, 46, 1101, 1000, 1, 20, 4, 20, 1105, 1, 46, 98, 99],
+];
+
+// xorshift64* so a failing run can be reproduced from its seed
+struct Rng {
+	state: u64,
+}
+
+impl Rng {
+	fn new(seed: u64) -> Self {
+		return Rng { state: seed.max(1) };
+	}
+
+	fn next(&mut self) -> u64 {
+		self.state ^= self.state >> 12;
+		self.state ^= self.state << 25;
+		self.state ^= self.state >> 27;
+		return self.state.wrapping_mul(0x2545F4914F6CDD1D);
+	}
+
+	fn below(&mut self, n: usize) -> usize {
+		return (self.next() % n.max(1) as u64) as usize;
+	}
+
+	fn chance(&mut self, percent: usize) -> bool {
+		return self.below(100) < percent;
+	}
+
+	fn pick<T: Copy>(&mut self, items: &[T]) -> T {
+		return items[self.below(items.len())];
+	}
+}
+
+// how a single run of a program ended
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Status {
+	Halted,			// reached the end
+	Faulted,		// stopped on a bad instruction
+	Stopped,		// out of steps or waiting for input
+}
+
+#[derive(Debug)]
+struct Outcome {
+	output: Vec<i64>,
+	status: Status,
+	common: bool,		// only executed instructions both CPUs share
+}
+
+#[derive(Default)]
+pub struct Report {
+	programs: usize,
+	compared: usize,
+	failures: Vec<String>,
+}
+
+impl Report {
+	pub fn show(&self) {
+		let programs = self.programs;
+		let compared = self.compared;
+		let failed = self.failures.len();
+		println!("fuzz: {programs} programs, {compared} compared, {failed} failures");
+
+		for failure in self.failures.iter().take(MAX_REPORTED) {
+			println!("{failure}");
+		}
+	}
+
+	pub fn passed(&self) -> bool {
+		return self.failures.is_empty();
+	}
+}
+
+// one instruction with random operands; `common` restricts it to what
+// both CPUs implement the same way
+fn random_instruction(rng: &mut Rng, common: bool, length: usize) -> Vec<i64> {
+	let junk = rng.next() as i64 % 100;
+	let (op, arity) = if common {
+		rng.pick(&[(1, 3), (2, 3), (3, 1), (4, 1), (5, 2), (6, 2), (7, 3), (8, 3), (99, 0)])
+	} else {
+		rng.pick(&[(1, 3), (2, 3), (3, 1), (4, 1), (5, 2), (6, 2), (7, 3), (8, 3),
+			(9, 1), (99, 0), (junk, 3)])
+	};
+
+	let mut instruction = vec![op];
+	let mut scale = 100;
+	for _ in 0..arity {
+		let mode = if common { rng.below(2) as i64 } else { rng.below(10) as i64 };
+		instruction[0] += mode * scale;
+		scale *= 10;
+
+		let operand = if rng.chance(70) {
+			rng.below(length) as i64
+		} else {
+			rng.pick(&INTERESTING)
+		};
+		instruction.push(operand);
+	}
+
+	if !common && rng.chance(5) {
+		instruction[0] = -instruction[0];
+	}
+
+	return instruction;
+}
+
+fn random_program(rng: &mut Rng, common: bool) -> Vec<i64> {
+	let length = 1 + rng.below(48);
+	let mut program = Vec::new();
+
+	while program.len() < length {
+		if rng.chance(10) {
+			program.push(rng.pick(&INTERESTING));
+		} else {
+			program.extend(random_instruction(rng, common, length));
+		}
+	}
+
+	return program;
+}
+
+fn mutate(rng: &mut Rng, seed: &[i64]) -> Vec<i64> {
+	let mut program = seed.to_vec();
+
+	for _ in 0..1 + rng.below(4) {
+		let at = rng.below(program.len());
+		match rng.below(6) {
+			0 if !program.is_empty() => program[at] = rng.pick(&INTERESTING),
+			1 if !program.is_empty() => program[at] = program[at].wrapping_add(rng.below(21) as i64 - 10),
+			2 if !program.is_empty() => {
+				// change one parameter mode digit
+				let digit = [100, 1000, 10000][rng.below(3)];
+				let mode = (program[at] / digit) % 10;
+				program[at] = program[at].wrapping_add((rng.below(10) as i64 - mode) * digit);
+			},
+			3 => program.insert(at.min(program.len()), rng.pick(&INTERESTING)),
+			4 if !program.is_empty() => { program.remove(at); },
+			_ => program.truncate(at),
+		}
+	}
+
+	return program;
+}
+
+// operands of the opcodes both CPUs know
+fn arity(op: i64) -> usize {
+	return match op {
+		1 | 2 | 7 | 8 => 3,
+		5 | 6 => 2,
+		3 | 4 => 1,
+		_ => 0,
+	};
+}
+
+// true if both CPUs interpret the instruction at pc the same way: relative
+// mode and opcode 9 only exist on the shared CPU, and only the shared CPU
+// reads and writes past the end of the program (day 9 memory)
+fn in_common_subset(memory: &[i64], pc: usize) -> bool {
+	let instruction = memory[pc];
+	let op = instruction % 100;
+	if op == 9 {
+		return false;
+	}
+
+	let mut scale = 100;
+	for i in 0..arity(op) {
+		let mode = (instruction / scale) % 10;
+		scale *= 10;
+
+		let Some(&operand) = memory.get(pc + 1 + i) else {
+			return false;
+		};
+		if mode == 2 || (mode == 0 && operand >= memory.len() as i64) {
+			return false;
+		}
+	}
+	return true;
+}
+
+fn run_shared(program: &[i64], input: &[i64]) -> Outcome {
+	let mut cpu = intcode::CPU::load_program(program);
+	cpu.set_memory_limit(MEMORY_BUDGET);
+	input.iter().for_each(|&value| cpu.push_input(value));
+
+	let mut steps = 0;
+	while steps < STEP_BUDGET && cpu.step() {
+		steps += 1;
+	}
+
+	let status = match (cpu.error(), cpu.is_terminated()) {
+		(Some(_), _) => Status::Faulted,
+		(None, true) => Status::Halted,
+		(None, false) => Status::Stopped,
+	};
+	return Outcome { status, common: true, output: cpu.output() };
+}
+
+fn run_day5(program: &[i64], input: &[i64]) -> Outcome {
+	let mut cpu = crate::build_cpu(program, input);
+
+	// programs can rewrite themselves, so check what actually executes
+	let mut common = true;
+	let mut steps = 0;
+	while steps < STEP_BUDGET {
+		if cpu.pc < cpu.memory.len() {
+			common &= in_common_subset(&cpu.memory, cpu.pc);
+		}
+
+		if !cpu.step() {
+			break;
+		}
+		steps += 1;
+	}
+
+	let status = match (cpu.fault, cpu.is_terminated()) {
+		(true, _) => Status::Faulted,
+		(false, true) => Status::Halted,
+		(false, false) => Status::Stopped,
+	};
+	return Outcome { status, common, output: cpu.output.drain(..).collect() };
+}
+
+// run one CPU, turning a panic into an error message
+fn guarded(run: fn(&[i64], &[i64]) -> Outcome, program: &[i64], input: &[i64]) -> Result<Outcome, String> {
+	return panic::catch_unwind(|| run(program, input)).map_err(|payload| {
+		if let Some(message) = payload.downcast_ref::<&str>() {
+			message.to_string()
+		} else if let Some(message) = payload.downcast_ref::<String>() {
+			message.clone()
+		} else {
+			"unknown panic".to_string()
+		}
+	});
+}
+
+fn check(report: &mut Report, program: &[i64], input: &[i64]) {
+	report.programs += 1;
+
+	let shared = guarded(run_shared, program, input);
+	let day5 = guarded(run_day5, program, input);
+
+	if let Err(message) = &shared {
+		report.failures.push(format!("shared CPU panicked ({message}) on {program:?} input {input:?}"));
+	}
+
+	if let Err(message) = &day5 {
+		report.failures.push(format!("day 5 CPU panicked ({message}) on {program:?} input {input:?}"));
+	}
+
+	if let (Ok(shared), Ok(day5)) = (shared, day5) {
+		let finished = shared.status != Status::Stopped && day5.status != Status::Stopped;
+		if finished && day5.common {
+			report.compared += 1;
+
+			if shared.status != day5.status || shared.output != day5.output {
+				let (a, b) = ((shared.status, shared.output), (day5.status, day5.output));
+				report.failures.push(format!("runs differ (shared {a:?}, day 5 {b:?}) on {program:?} input {input:?}"));
+			}
+		}
+	}
+}
+
+pub fn fuzz(iterations: usize, seed: u64) -> Report {
+	let mut rng = Rng::new(seed);
+	let mut report = Report::default();
+
+	// silence the default hook, panics are collected into the report
+	let hook = panic::take_hook();
+	panic::set_hook(Box::new(|_| {}));
+
+	for i in 0..iterations {
+		let program = match i % 3 {
+			0 => random_program(&mut rng, true),
+			1 => random_program(&mut rng, false),
+			_ => {
+				let seed = rng.pick(&SEEDS);
+				mutate(&mut rng, seed)
+			},
+		};
+
+		let input: Vec<i64> = (0..rng.below(4))
+			.map(|_| if rng.chance(50) { rng.below(16) as i64 } else { rng.pick(&INTERESTING) })
+			.collect();
+
+		check(&mut report, &program, &input);
+	}
+
+	panic::set_hook(hook);
+	return report;
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn cpus_agree() {
+		let report = fuzz(3_000, 2019);
+
+		assert!(report.compared > 0, "nothing was compared");
+		assert!(report.passed(), "{}", report.failures.join("\n"));
+	}
+}
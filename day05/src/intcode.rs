@@ -0,0 +1,937 @@
+use std::fs;
+use std::fmt;
+use std::io;
+use std::rc::Rc;
+use std::collections::{HashSet, VecDeque};
+
+// largest memory (in cells) a program may grow to before it is stopped
+const MEMORY_LIMIT: usize = 1 << 24;
+
+// Reasons the CPU stops on a malformed program instead of panicking
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CpuError {
+    BadAddress(i64),        // negative (or too large) memory address
+    BadMode(i64),           // parameter mode not in the mode table
+    ImmediateWrite,         // write parameter in immediate mode
+    BadJump(i64),           // negative jump target
+    UnknownOp(i64),         // opcode not in the instruction table
+    Overflow,               // arithmetic overflowed an i64
+    MemoryLimit(usize),     // program tried to grow memory past the limit
+    NeedInput,              // program waiting on input that will never come
+}
+
+impl fmt::Display for CpuError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            CpuError::BadAddress(a) => write!(f, "bad memory address {a}"),
+            CpuError::BadMode(m) => write!(f, "bad parameter mode {m}"),
+            CpuError::ImmediateWrite => write!(f, "write in immediate mode"),
+            CpuError::BadJump(a) => write!(f, "bad jump target {a}"),
+            CpuError::UnknownOp(op) => write!(f, "unknown operation {op}"),
+            CpuError::Overflow => write!(f, "arithmetic overflow"),
+            CpuError::MemoryLimit(a) => write!(f, "address {a} past memory limit"),
+            CpuError::NeedInput => write!(f, "waiting for input"),
+        };
+    }
+}
+
+// One I/O event of a recorded session, stamped with the number of
+// instructions the CPU had run when it happened
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+    Input(u64, i64),
+    Output(u64, i64),
+}
+
+impl Event {
+    // instructions run when it happened
+    pub fn cycle(&self) -> u64 {
+        return match self {
+            Event::Input(cycle, _) | Event::Output(cycle, _) => *cycle,
+        };
+    }
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            Event::Input(cycle, value) => write!(f, "in {cycle} {value}"),
+            Event::Output(cycle, value) => write!(f, "out {cycle} {value}"),
+        };
+    }
+}
+
+// How to take back a stretch of execution: the registers, memory size and
+// journal length from before it and the old value of every cell written
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Undo {
+    pub pc: usize,
+    pub base: i64,
+    pub cycles: u64,
+    pub length: usize,              // memory cells
+    pub journal: usize,             // journal events
+    pub writes: Vec<(usize, i64)>,  // (address, old value), first write of each cell
+}
+
+// Every input consumed and output produced during a run, in order
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Journal {
+    pub events: Vec<Event>,
+}
+
+impl Journal {
+    // one event per line, "in <cycle> <value>" or "out <cycle> <value>"
+    #[allow(dead_code)]
+    pub fn save(&self, filename: &str) -> io::Result<()> {
+        let text: String = self.events.iter()
+            .map(|event| format!("{event}\n"))
+            .collect();
+
+        return fs::write(filename, text);
+    }
+
+    #[allow(dead_code)]
+    pub fn load(filename: &str) -> Result<Journal, String> {
+        let text = fs::read_to_string(filename).map_err(|e| format!("{filename}: {e}"))?;
+        let mut events = Vec::new();
+
+        for (n, line) in text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+            let bad = || format!("{filename}:{}: bad event '{line}'", n + 1);
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() != 3 {
+                return Err(bad());
+            }
+
+            let cycle = fields[1].parse().map_err(|_| bad())?;
+            let value = fields[2].parse().map_err(|_| bad())?;
+            events.push(match fields[0] {
+                "in" => Event::Input(cycle, value),
+                "out" => Event::Output(cycle, value),
+                _ => return Err(bad()),
+            });
+        }
+
+        return Ok(Journal { events });
+    }
+
+    // values that were fed to the program, in order
+    #[allow(dead_code)]
+    pub fn inputs(&self) -> Vec<i64> {
+        return self.events.iter()
+            .filter_map(|event| match event {
+                Event::Input(_, value) => Some(*value),
+                Event::Output(_, _) => None,
+            })
+            .collect();
+    }
+}
+
+// Where a replayed run stopped matching its journal
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Divergence {
+    Mismatch(usize, Event, Event),  // index, expected, actual
+    Missing(usize, Event),          // program stopped short of this event
+    Extra(usize, Event),            // program went on past the end of the journal
+    Fault(CpuError),                // program failed during replay
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            Divergence::Mismatch(i, expected, actual) => write!(f, "event {i}: expected '{expected}', got '{actual}'"),
+            Divergence::Missing(i, expected) => write!(f, "event {i}: expected '{expected}', program stopped"),
+            Divergence::Extra(i, actual) => write!(f, "event {i}: unexpected '{actual}'"),
+            Divergence::Fault(error) => write!(f, "program failed: {error}"),
+        };
+    }
+}
+
+// Registers and memory of a CPU, enough to pick a program up again where
+// it was left as long as no input or output is queued
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CpuState {
+    pub pc: usize,
+    pub base: i64,
+    pub cycles: u64,
+    pub memory: Vec<i64>,
+}
+
+// An operand after its parameter mode has been applied
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operand {
+    Value(i64),         // use as is (immediate)
+    Address(usize),     // read from or write to memory
+}
+
+// What the CPU does after an instruction has run
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Flow {
+    Next,               // continue after this instruction's operands
+    Jump(usize),        // continue at address
+    Wait,               // stay on this instruction (e.g. waiting for input)
+    Halt,               // stop the program
+}
+
+// Parameter mode: turns the raw operand into a value or address
+#[derive(Clone, Copy)]
+pub struct Mode {
+    pub prefix: &'static str,   // marks the operand in debug listings
+    pub resolve: fn(&CPU, i64) -> Result<Operand, CpuError>,
+}
+
+// Handlers get the operands already resolved: the value for reads and the
+// address for writes.
+pub type Handler = Rc<dyn Fn(&mut CPU, &[i64]) -> Result<Flow, CpuError>>;
+
+// most operands an instruction can have
+pub const MAX_ARITY: usize = 3;
+
+// An instruction: mnemonic, number of operands (up to MAX_ARITY), which of
+// them are written to and the code that runs it.
+#[derive(Clone)]
+pub struct Instruction {
+    pub name: &'static str,
+    pub arity: usize,
+    pub writes: &'static [usize],
+    pub handler: Handler,
+}
+
+impl Instruction {
+    pub fn new<F>(name: &'static str, arity: usize, writes: &'static [usize], handler: F) -> Self
+    where
+        F: Fn(&mut CPU, &[i64]) -> Result<Flow, CpuError> + 'static,
+    {
+        assert!(arity <= MAX_ARITY, "{name} has {arity} operands, at most {MAX_ARITY} are decoded");
+        return Instruction { name, arity, writes, handler: Rc::new(handler) };
+    }
+}
+
+impl fmt::Debug for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "{}/{}", self.name, self.arity);
+    }
+}
+
+impl fmt::Debug for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "mode '{}'", self.prefix);
+    }
+}
+
+// Opcode (0-99) and parameter mode (0-9) tables, shared between clones of
+// a CPU until one of them registers something new
+#[derive(Clone, Debug)]
+struct InstructionSet {
+    instructions: Vec<Option<Instruction>>,
+    modes: Vec<Option<Mode>>,
+}
+
+impl InstructionSet {
+    // the day 9 instruction set
+    fn standard() -> Self {
+        let mut set = InstructionSet {
+            instructions: vec![None; 100],
+            modes: vec![None; 10],
+        };
+
+        set.modes[0] = Some(Mode { prefix: "", resolve: position });
+        set.modes[1] = Some(Mode { prefix: "$", resolve: immediate });
+        set.modes[2] = Some(Mode { prefix: "+", resolve: relative });
+
+        set.instructions[1] = Some(Instruction::new("add", 3, &[2], add));
+        set.instructions[2] = Some(Instruction::new("mul", 3, &[2], mul));
+        set.instructions[3] = Some(Instruction::new("inp", 1, &[0], inp));
+        set.instructions[4] = Some(Instruction::new("out", 1, &[], out));
+        set.instructions[5] = Some(Instruction::new("jit", 2, &[], jit));
+        set.instructions[6] = Some(Instruction::new("jif", 2, &[], jif));
+        set.instructions[7] = Some(Instruction::new("lt", 3, &[2], lt));
+        set.instructions[8] = Some(Instruction::new("eq", 3, &[2], eq));
+        set.instructions[9] = Some(Instruction::new("bas", 1, &[], bas));
+        set.instructions[99] = Some(Instruction::new("end", 0, &[], end));
+
+        return set;
+    }
+}
+
+fn to_address(address: i64) -> Result<usize, CpuError> {
+    return usize::try_from(address).map_err(|_| CpuError::BadAddress(address));
+}
+
+fn position(_cpu: &CPU, operand: i64) -> Result<Operand, CpuError> {
+    return Ok(Operand::Address(to_address(operand)?));
+}
+
+fn immediate(_cpu: &CPU, operand: i64) -> Result<Operand, CpuError> {
+    return Ok(Operand::Value(operand));
+}
+
+fn relative(cpu: &CPU, operand: i64) -> Result<Operand, CpuError> {
+    let address = cpu.base.checked_add(operand).ok_or(CpuError::Overflow)?;
+    return Ok(Operand::Address(to_address(address)?));
+}
+
+fn add(cpu: &mut CPU, o: &[i64]) -> Result<Flow, CpuError> {
+    let value = o[0].checked_add(o[1]).ok_or(CpuError::Overflow)?;
+    cpu.write(o[2], value)?;
+    return Ok(Flow::Next);
+}
+
+fn mul(cpu: &mut CPU, o: &[i64]) -> Result<Flow, CpuError> {
+    let value = o[0].checked_mul(o[1]).ok_or(CpuError::Overflow)?;
+    cpu.write(o[2], value)?;
+    return Ok(Flow::Next);
+}
+
+fn inp(cpu: &mut CPU, o: &[i64]) -> Result<Flow, CpuError> {
+    return match cpu.pop_input() {
+        Some(value) => {
+            if cpu.debug {
+                println!("\t<== {value}");
+            }
+
+            cpu.write(o[0], value)?;
+            Ok(Flow::Next)
+        },
+        None => {
+            if cpu.debug {
+                println!("\t<< No input available (pause)\n");
+            }
+
+            Ok(Flow::Wait)
+        },
+    };
+}
+
+fn out(cpu: &mut CPU, o: &[i64]) -> Result<Flow, CpuError> {
+    if cpu.debug {
+        println!("\t==> {}", o[0]);
+    }
+
+    cpu.push_output(o[0]);
+    return Ok(Flow::Next);
+}
+
+fn jit(_cpu: &mut CPU, o: &[i64]) -> Result<Flow, CpuError> {
+    if o[0] != 0 {
+        return Ok(Flow::Jump(usize::try_from(o[1]).map_err(|_| CpuError::BadJump(o[1]))?));
+    }
+
+    return Ok(Flow::Next);
+}
+
+fn jif(_cpu: &mut CPU, o: &[i64]) -> Result<Flow, CpuError> {
+    if o[0] == 0 {
+        return Ok(Flow::Jump(usize::try_from(o[1]).map_err(|_| CpuError::BadJump(o[1]))?));
+    }
+
+    return Ok(Flow::Next);
+}
+
+fn lt(cpu: &mut CPU, o: &[i64]) -> Result<Flow, CpuError> {
+    cpu.write(o[2], if o[0] < o[1] { 1 } else { 0 })?;
+    return Ok(Flow::Next);
+}
+
+fn eq(cpu: &mut CPU, o: &[i64]) -> Result<Flow, CpuError> {
+    cpu.write(o[2], if o[0] == o[1] { 1 } else { 0 })?;
+    return Ok(Flow::Next);
+}
+
+fn bas(cpu: &mut CPU, o: &[i64]) -> Result<Flow, CpuError> {
+    cpu.base = cpu.base.checked_add(o[0]).ok_or(CpuError::Overflow)?;
+    return Ok(Flow::Next);
+}
+
+fn end(_cpu: &mut CPU, _o: &[i64]) -> Result<Flow, CpuError> {
+    return Ok(Flow::Halt);
+}
+
+thread_local! {
+    // one copy of the standard tables for every CPU loaded, a CPU gets its
+    // own when it registers something
+    static STANDARD: Rc<InstructionSet> = Rc::new(InstructionSet::standard());
+}
+
+#[derive(Clone, Debug)]
+pub struct CPU {
+    pc: usize,        // program counter
+    memory: Vec<i64>, // memory
+    base: i64,        // relative base (day 9)
+    input: VecDeque<i64>,
+    output: VecDeque<i64>,
+    error: Option<CpuError>,
+    memory_limit: usize,
+    set: Rc<InstructionSet>,
+    cycles: u64,                // instructions run so far
+    journal: Option<Journal>,   // I/O log while recording
+    undo: Option<Undo>,         // memory writes while tracing
+    debug: bool,
+}
+
+impl CPU {
+    pub fn load_program(program: &[i64]) -> CPU {
+        return CPU {
+            pc: 0,
+            memory: program.to_vec(),
+            base: 0,
+            input: VecDeque::new(),
+            output: VecDeque::new(),
+            error: None,
+            memory_limit: MEMORY_LIMIT,
+            set: STANDARD.with(Rc::clone),
+            cycles: 0,
+            journal: None,
+            undo: None,
+            debug: false,
+        };
+    }
+
+    // patch the program before running it, only inside the program
+    pub fn set_memory(&mut self, address: usize, value: i64) -> Result<(), CpuError> {
+        let cell = self.memory.get_mut(address)
+            .ok_or(CpuError::BadAddress(i64::try_from(address).unwrap_or(i64::MAX)))?;
+        *cell = value;
+        return Ok(());
+    }
+
+    #[allow(dead_code)]
+    pub fn get_memory(&self, address: usize) -> i64 {
+        return self.load_direct(address);
+    }
+
+    #[allow(dead_code)]
+    pub fn set_memory_limit(&mut self, limit: usize) {
+        self.memory_limit = limit;
+    }
+
+    // add or replace the instruction for opcode (0-99), e.g. a debug
+    // opcode that prints its operand:
+    //
+    //     cpu.register(42, Instruction::new("dbg", 1, &[], |_cpu, o| {
+    //         println!("dbg {}", o[0]);
+    //         return Ok(Flow::Next);
+    //     }))?;
+    //
+    // Opcodes past 99 can't be written in an instruction, they are an error
+    #[allow(dead_code)]
+    pub fn register(&mut self, op: usize, instruction: Instruction) -> Result<(), CpuError> {
+        if op >= self.set.instructions.len() {
+            return Err(CpuError::UnknownOp(i64::try_from(op).unwrap_or(i64::MAX)));
+        }
+
+        Rc::make_mut(&mut self.set).instructions[op] = Some(instruction);
+        return Ok(());
+    }
+
+    // add or replace parameter mode (0-9), other digits are an error
+    #[allow(dead_code)]
+    pub fn register_mode(&mut self, digit: usize, mode: Mode) -> Result<(), CpuError> {
+        if digit >= self.set.modes.len() {
+            return Err(CpuError::BadMode(i64::try_from(digit).unwrap_or(i64::MAX)));
+        }
+
+        Rc::make_mut(&mut self.set).modes[digit] = Some(mode);
+        return Ok(());
+    }
+
+    #[allow(dead_code)]
+    pub fn relative_base(&self) -> i64 {
+        return self.base;
+    }
+
+    fn load_direct(&self, address: usize) -> i64 {
+        if self.memory.len() <= address {
+            return 0;
+        }
+
+        return self.memory[address];
+    }
+
+    // store value at address (as handed to a handler for a write operand)
+    pub fn write(&mut self, address: i64, value: i64) -> Result<(), CpuError> {
+        let address = to_address(address)?;
+
+        if self.memory.len() <= address {
+            if address >= self.memory_limit {
+                return Err(CpuError::MemoryLimit(address));
+            }
+
+            self.memory.resize(address+1, 0);
+        }
+
+        // cells past the old end go when the memory is cut back on undo
+        if let Some(undo) = &mut self.undo && address < undo.length {
+            undo.writes.push((address, self.memory[address]));
+        }
+
+        // println!("WRITE: {address}: {value}");
+        self.memory[address] = value;
+        return Ok(());
+    }
+
+    // apply parameter mode to the operand at address
+    fn operand(&self, address: usize, mode: i64, write: bool) -> Result<i64, CpuError> {
+        let raw = self.load_direct(address);
+        let resolve = usize::try_from(mode)
+            .ok()
+            .and_then(|m| self.set.modes.get(m).copied().flatten())
+            .ok_or(CpuError::BadMode(mode))?
+            .resolve;
+
+        return match (resolve(self, raw)?, write) {
+            (Operand::Value(_), true) => Err(CpuError::ImmediateWrite),
+            (Operand::Value(value), false) => Ok(value),
+            (Operand::Address(address), true) => Ok(address as i64),
+            (Operand::Address(address), false) => Ok(self.load_direct(address)),
+        };
+    }
+
+    pub fn is_terminated(&self) -> bool {
+        return self.pc >= self.memory.len() || self.error.is_some();
+    }
+
+    // error that stopped the CPU, if any
+    #[allow(dead_code)]
+    pub fn error(&self) -> Option<CpuError> {
+        return self.error;
+    }
+
+    // decode and run the instruction at pc
+    fn execute_instruction(&mut self) -> Result<bool, CpuError> {
+        let instruction = self.memory[self.pc];
+        let op = instruction % 100;
+
+        // the handler gets the CPU, so the entry is borrowed from a second
+        // handle on the table rather than from self
+        let set = Rc::clone(&self.set);
+        let entry = usize::try_from(op)
+            .ok()
+            .and_then(|op| set.instructions[op].as_ref())
+            .ok_or(CpuError::UnknownOp(op))?;
+
+        let mut modes = [0; MAX_ARITY];
+        let mut operands = [0; MAX_ARITY];
+        let mut scale: i64 = 100;
+        for i in 0..entry.arity {
+            modes[i] = (instruction / scale) % 10;
+            operands[i] = self.operand(self.pc + 1 + i, modes[i], entry.writes.contains(&i))?;
+            scale = scale.saturating_mul(10);
+        }
+
+        if self.debug {
+            let listing: Vec<String> = modes[..entry.arity].iter()
+                .enumerate()
+                .map(|(i, &mode)| self.format_operand(self.pc + 1 + i, mode))
+                .collect();
+            println!("{}\t{}", entry.name, listing.join(", "));
+        }
+
+        let flow = (entry.handler)(self, &operands[..entry.arity])?;
+        if flow != Flow::Wait {
+            self.cycles += 1;
+        }
+
+        return match flow {
+            Flow::Next => {
+                self.pc += entry.arity + 1;
+                Ok(true)
+            },
+            Flow::Jump(address) => {
+                self.pc = address;
+                Ok(true)
+            },
+            Flow::Wait => Ok(false),
+            Flow::Halt => {
+                if self.debug {
+                    println!();
+                }
+                self.pc = self.memory.len();
+                Ok(true)
+            },
+        };
+    }
+
+    pub fn step(&mut self) -> bool {
+        if self.is_terminated() {
+            return false;
+        }
+
+        let pc = self.pc;
+        if self.debug {
+            print!("{pc:04X}:\t");
+        }
+
+        return match self.execute_instruction() {
+            Ok(running) => running,
+            Err(error) => {
+                if self.debug {
+                    println!("{error}");
+                }
+
+                // leave pc on the faulting instruction for inspection
+                self.pc = pc;
+                self.error = Some(error);
+                false
+            }
+        };
+    }
+
+    pub fn run(&mut self) {
+        // self.show();
+        while self.step() {
+            // self.show();
+        }
+    }
+
+    // run a program that takes no (more) input through to the end
+    #[allow(dead_code)]
+    pub fn execute(&mut self) -> Result<(), CpuError> {
+        self.run();
+
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+
+        if !self.is_terminated() {
+            return Err(CpuError::NeedInput);
+        }
+
+        return Ok(());
+    }
+
+    #[allow(dead_code)]
+    pub fn show(&self) {
+        for (i, v) in self.memory.iter().enumerate() {
+            if i == self.pc {
+                print!("({v})");
+            } else {
+                print!("{v}");
+            }
+            if i < self.memory.len() - 1 {
+                print!(",");
+            }
+        }
+        println!("")
+    }
+
+    #[allow(dead_code)]
+    fn format_operand(&self, address: usize, mode: i64) -> String {
+        let operand = self.load_direct(address);
+
+        return match usize::try_from(mode).ok().and_then(|m| self.set.modes.get(m).copied().flatten()) {
+            Some(mode) => format!("{}{operand}", mode.prefix),
+            None => "Error".to_string(),
+        };
+    }
+
+    #[allow(dead_code)]
+    pub fn show_output(&self) {
+        println!("{:?}", self.output);
+    }
+
+    #[allow(dead_code)]
+    pub fn push_input(&mut self, data: i64) {
+        self.input.push_back(data);
+    }
+
+    // take the next input for the program, recorded if journaling
+    #[allow(dead_code)]
+    pub fn pop_input(&mut self) -> Option<i64> {
+        let value = self.input.pop_front()?;
+
+        if let Some(journal) = &mut self.journal {
+            journal.events.push(Event::Input(self.cycles, value));
+        }
+
+        return Some(value);
+    }
+
+    // hand a value out of the program, recorded if journaling
+    #[allow(dead_code)]
+    pub fn push_output(&mut self, data: i64) {
+        if let Some(journal) = &mut self.journal {
+            journal.events.push(Event::Output(self.cycles, data));
+        }
+
+        self.output.push_back(data);
+    }
+
+    // start logging I/O from here on, dropping anything logged before
+    #[allow(dead_code)]
+    pub fn start_recording(&mut self) {
+        self.journal = Some(Journal::default());
+    }
+
+    // stop logging and hand back what was logged
+    #[allow(dead_code)]
+    pub fn take_journal(&mut self) -> Option<Journal> {
+        return self.journal.take();
+    }
+
+    // start tracing memory writes so what runs from here can be undone
+    #[allow(dead_code)]
+    pub fn start_undo(&mut self) {
+        self.undo = Some(Undo {
+            pc: self.pc,
+            base: self.base,
+            cycles: self.cycles,
+            length: self.memory.len(),
+            journal: self.journal.as_ref().map_or(0, |journal| journal.events.len()),
+            writes: Vec::new(),
+        });
+    }
+
+    // stop tracing and hand back the undo, keeping one write per cell
+    #[allow(dead_code)]
+    pub fn take_undo(&mut self) -> Option<Undo> {
+        let mut undo = self.undo.take()?;
+        let mut seen = HashSet::new();
+        undo.writes.retain(|&(address, _)| seen.insert(address));
+        return Some(undo);
+    }
+
+    // put the CPU back the way it was when start_undo() was called,
+    // dropping queued I/O and any error
+    #[allow(dead_code)]
+    pub fn apply_undo(&mut self, undo: &Undo) {
+        for &(address, value) in undo.writes.iter().rev() {
+            self.memory[address] = value;
+        }
+        self.memory.truncate(undo.length);
+
+        self.pc = undo.pc;
+        self.base = undo.base;
+        self.cycles = undo.cycles;
+        if let Some(journal) = &mut self.journal {
+            journal.events.truncate(undo.journal);
+        }
+
+        self.input.clear();
+        self.output.clear();
+        self.error = None;
+    }
+
+    // a copy to run ahead on, without the journal or undo trace
+    #[allow(dead_code)]
+    pub fn fork(&self) -> CPU {
+        return CPU {
+            pc: self.pc,
+            memory: self.memory.clone(),
+            base: self.base,
+            input: self.input.clone(),
+            output: self.output.clone(),
+            error: self.error,
+            memory_limit: self.memory_limit,
+            set: Rc::clone(&self.set),
+            cycles: self.cycles,
+            journal: None,
+            undo: None,
+            debug: false,
+        };
+    }
+
+    // go back to an earlier fork of this CPU, keeping what the journal
+    // logged up to it
+    #[allow(dead_code)]
+    pub fn rewind_to(&mut self, fork: &CPU) {
+        let journal = self.journal.take();
+        *self = fork.fork();
+
+        self.journal = journal.map(|mut journal| {
+            journal.events.retain(|event| event.cycle() < fork.cycles);
+            journal
+        });
+    }
+
+    #[allow(dead_code)]
+    pub fn journal(&self) -> Option<&Journal> {
+        return self.journal.as_ref();
+    }
+
+    #[allow(dead_code)]
+    pub fn cycles(&self) -> u64 {
+        return self.cycles;
+    }
+
+    #[allow(dead_code)]
+    pub fn state(&self) -> CpuState {
+        return CpuState {
+            pc: self.pc,
+            base: self.base,
+            cycles: self.cycles,
+            memory: self.memory.clone(),
+        };
+    }
+
+    // put registers and memory back, clearing queued I/O and any error
+    #[allow(dead_code)]
+    pub fn set_state(&mut self, state: &CpuState) {
+        self.pc = state.pc;
+        self.base = state.base;
+        self.cycles = state.cycles;
+        self.memory = state.memory.clone();
+        self.input.clear();
+        self.output.clear();
+        self.error = None;
+    }
+
+    #[allow(dead_code)]
+    pub fn output_len(&mut self) -> usize {
+        return self.output.len();
+    }
+
+    #[allow(dead_code)]
+    pub fn output(&mut self) -> Vec<i64> {
+        return self.output.drain(..).collect();
+        // return self.output.clone().into();
+    }
+
+    #[allow(dead_code)]
+    pub fn pop_output(&mut self) -> Option<i64> {
+        return self.output.pop_front();
+        // return match self.output.pop_front() {
+        //     Some(out) => out,
+        //     None => 0
+        // };
+    }
+}
+
+// Run cpu feeding it the inputs from journal whenever it waits, checking
+// every event against the journal. A journal that runs out while the
+// program waits for input just ends the replay (the session was left there).
+#[allow(dead_code)]
+pub fn replay(cpu: &mut CPU, journal: &Journal) -> Result<(), Divergence> {
+    let expected = &journal.events;
+    cpu.start_recording();
+
+    let mut checked = 0;
+    loop {
+        cpu.run();
+
+        let actual = &cpu.journal().unwrap().events;
+        while checked < actual.len() {
+            match expected.get(checked) {
+                Some(e) if *e != actual[checked] => return Err(Divergence::Mismatch(checked, *e, actual[checked])),
+                Some(_) => checked += 1,
+                None => return Err(Divergence::Extra(checked, actual[checked])),
+            }
+        }
+
+        if let Some(error) = cpu.error() {
+            return Err(Divergence::Fault(error));
+        }
+
+        match expected.get(checked) {
+            None => return Ok(()),
+            Some(Event::Input(_, value)) if !cpu.is_terminated() => cpu.push_input(*value),
+            Some(event) => return Err(Divergence::Missing(checked, *event)),
+        }
+    }
+}
+
+#[allow(dead_code)]
+pub fn build_cpu(program: &[i64], input: &[i64]) -> CPU {
+    let mut cpu = CPU::load_program(program);
+    input.iter().for_each(|&value| cpu.push_input(value));
+    return cpu;
+}
+
+pub fn read_program(filename: &str) -> Vec<i64> {
+    fs::read_to_string(filename)
+        .expect("Expected input file to exist")
+        .replace("\n", "")
+        .split(',')
+        .map(|line| line.parse::<i64>().unwrap())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // write twice the first operand to the second
+    fn double(cpu: &mut CPU, o: &[i64]) -> Result<Flow, CpuError> {
+        let value = o[0].checked_mul(2).ok_or(CpuError::Overflow)?;
+        cpu.write(o[1], value)?;
+        return Ok(Flow::Next);
+    }
+
+    // the operand negated, as an immediate value
+    fn negated(_cpu: &CPU, operand: i64) -> Result<Operand, CpuError> {
+        return Ok(Operand::Value(operand.checked_neg().ok_or(CpuError::Overflow)?));
+    }
+
+    #[test]
+    fn custom_instructions_and_modes() {
+        // dbl -5 into 7, dbl m7 into 8
+        let program = [342, 5, 7, 42, 7, 8, 99, 0, 0];
+        let standard = CPU::load_program(&program);
+
+        let mut cpu = standard.clone();
+        cpu.register(42, Instruction::new("dbl", 2, &[1], double)).unwrap();
+        cpu.register_mode(3, Mode { prefix: "-", resolve: negated }).unwrap();
+        assert_eq!(cpu.execute(), Ok(()));
+        assert_eq!((cpu.get_memory(7), cpu.get_memory(8)), (-10, -20));
+
+        // only the CPU that registered them has them
+        let mut cpu = standard.clone();
+        assert_eq!(cpu.execute(), Err(CpuError::UnknownOp(42)));
+        let mut cpu = standard;
+        cpu.register(42, Instruction::new("dbl", 2, &[1], double)).unwrap();
+        assert_eq!(cpu.execute(), Err(CpuError::BadMode(3)));
+    }
+
+    // doubles one input: in m9, m10 = m9 * 2, out m10
+    const DOUBLE: [i64; 11] = [3, 9, 1002, 9, 2, 10, 4, 10, 99, 0, 0];
+
+    fn record(input: i64) -> Journal {
+        let mut cpu = build_cpu(&DOUBLE, &[input]);
+        cpu.start_recording();
+        cpu.run();
+        return cpu.take_journal().unwrap();
+    }
+
+    #[test]
+    fn journal_saves_and_loads() {
+        let journal = record(21);
+        assert_eq!(journal.inputs(), vec![21]);
+        assert!(matches!(journal.events[..], [Event::Input(_, 21), Event::Output(_, 42)]));
+
+        let filename = std::env::temp_dir().join(format!("journal-{}.txt", std::process::id()));
+        let filename = filename.to_str().unwrap();
+        journal.save(filename).unwrap();
+        let loaded = Journal::load(filename);
+        fs::write(filename, "in 1 2\nout x 3\n").unwrap();
+        let bad = Journal::load(filename);
+        fs::remove_file(filename).unwrap();
+
+        assert_eq!(loaded, Ok(journal));
+        assert_eq!(bad, Err(format!("{filename}:2: bad event 'out x 3'")));
+    }
+
+    #[test]
+    fn replay_finds_divergences() {
+        let journal = record(21);
+        let (input, output) = (journal.events[0], journal.events[1]);
+        let replay = |events: Vec<Event>| replay(&mut CPU::load_program(&DOUBLE), &Journal { events });
+
+        assert_eq!(replay(vec![input, output]), Ok(()));
+
+        let Event::Output(cycle, _) = output else { panic!("{output} is not an output") };
+        let wrong = Event::Output(cycle, 43);
+        assert_eq!(replay(vec![input, wrong]), Err(Divergence::Mismatch(1, wrong, output)));
+
+        let more = Event::Input(cycle + 1, 5);
+        assert_eq!(replay(vec![input, output, more]), Err(Divergence::Missing(2, more)));
+        assert_eq!(replay(vec![input]), Err(Divergence::Extra(1, output)));
+    }
+
+    #[test]
+    fn registering_out_of_range() {
+        let mut cpu = CPU::load_program(&[99]);
+        let instruction = Instruction::new("dbl", 2, &[1], double);
+        assert_eq!(cpu.register(100, instruction), Err(CpuError::UnknownOp(100)));
+        assert_eq!(cpu.register_mode(10, Mode { prefix: "-", resolve: negated }), Err(CpuError::BadMode(10)));
+        assert_eq!(cpu.execute(), Ok(()));
+    }
+}
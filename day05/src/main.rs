@@ -1,168 +1,202 @@
 use std::env;
 use std::fs;
 use std::process;
-use std::time::Instant;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 use std::collections::VecDeque;
 use std::iter::FromIterator;
+pub mod intcode;
+mod fuzz;
 
 struct CPU {
 	pc: usize,				// program counter
 	memory: Vec<i64>,		// memory
 	input: VecDeque<i64>,
 	output: VecDeque<i64>,
+	fault: bool,			// stopped on a malformed instruction
 }
 
 impl CPU {
-	fn store(&mut self, address: usize, value: i64) {
-		self.memory[address] = value;
+	fn store(&mut self, address: usize, value: i64) -> Option<()> {
+		*self.memory.get_mut(address)? = value;
+		return Some(());
 	}
 
-	fn load(&self, address: usize, direct: i64) -> i64 {
-		let operand = self.memory[address];
+	// mode 0 is position, 1 immediate (direct), anything else is a fault
+	fn load(&self, address: usize, mode: i64) -> Option<i64> {
+		let operand = *self.memory.get(address)?;
 
-		if direct == 0 {
-			let address = usize::try_from(operand).unwrap();
-			return self.memory[address];
-		}
+		return match mode {
+			0 => self.memory.get(usize::try_from(operand).ok()?).copied(),
+			1 => Some(operand),
+			_ => None,
+		};
+	}
 
-		return operand;
+	// writes are always position mode, the address is used as is
+	fn target(&self, address: usize, mode: i64) -> Option<usize> {
+		if mode != 0 {
+			return None;
+		}
+		return usize::try_from(self.load(address, 1)?).ok();
 	}
 
-	fn add(&mut self, mode1: i64, mode2: i64) {
-		let o1 = self.load(self.pc+1, mode1);
-		let o2 = self.load(self.pc+2, mode2);
-		let o3 = self.load(self.pc+3, 1);	// writes always use direct
+	fn add(&mut self, mode1: i64, mode2: i64, mode3: i64) -> Option<()> {
+		let o1 = self.load(self.pc+1, mode1)?;
+		let o2 = self.load(self.pc+2, mode2)?;
+		let o3 = self.target(self.pc+3, mode3)?;
 		// println!("add {o1}:{mode1} + {o2}:{mode2} => {o3}");
 
-		self.store(usize::try_from(o3).unwrap(), o1 + o2);
+		self.store(o3, o1.checked_add(o2)?)?;
 		self.pc += 4;
+		return Some(());
 	}
 
-	fn mul(&mut self, mode1: i64, mode2: i64) {
-		let o1 = self.load(self.pc+1, mode1);
-		let o2 = self.load(self.pc+2, mode2);
-		let o3 = self.load(self.pc+3, 1);	// writes always use direct
+	fn mul(&mut self, mode1: i64, mode2: i64, mode3: i64) -> Option<()> {
+		let o1 = self.load(self.pc+1, mode1)?;
+		let o2 = self.load(self.pc+2, mode2)?;
+		let o3 = self.target(self.pc+3, mode3)?;
 		// println!("mul {o1}:{mode1} * {o2}:{mode2} => {o3}");
 
-		self.store(usize::try_from(o3).unwrap(), o1 * o2);
+		self.store(o3, o1.checked_mul(o2)?)?;
 		self.pc += 4;
+		return Some(());
 	}
 
-	fn inp(&mut self, _mode1: i64) {
-		if self.input.len() >= 1 {
-			let value = self.input.pop_front().unwrap();
+	fn inp(&mut self, mode1: i64) -> Option<()> {
+		let value = *self.input.front()?;
+		let address = self.target(self.pc+1, mode1)?;
 
-			let o1 = self.load(self.pc+1, 1);	// writes always use direct
-			let address = usize::try_from(o1).unwrap();
-
-			self.store(address, value);
-			self.pc += 2;
+		self.store(address, value)?;
+		self.input.pop_front();
+		self.pc += 2;
 
-			// println!("inp {address}:{_mode1} => {value}");
-		}
+		// println!("inp {address}:{mode1} => {value}");
+		return Some(());
 	}
 
-	fn out(&mut self, mode1: i64) {
-		let value = self.load(self.pc+1, mode1);
+	fn out(&mut self, mode1: i64) -> Option<()> {
+		let value = self.load(self.pc+1, mode1)?;
 		self.output.push_back(value);
 		self.pc += 2;
 
 		// let address = self.memory[self.pc+1];
 		// println!("out {address}:{mode1} => {value}");
+		return Some(());
 	}
 
-	fn jit(&mut self, mode1: i64, mode2: i64) {
-		let o1 = self.load(self.pc+1, mode1);
-		let o2 = self.load(self.pc+2, mode2);
+	fn jit(&mut self, mode1: i64, mode2: i64) -> Option<()> {
+		let o1 = self.load(self.pc+1, mode1)?;
+		let o2 = self.load(self.pc+2, mode2)?;
 		// println!("jit {o1}:{mode1}, {o2}:{mode2}");
 
 		if o1 != 0 {
-			self.pc = usize::try_from(o2).unwrap();
+			self.pc = usize::try_from(o2).ok()?;
 		} else {
 			self.pc += 3;
 		}
+		return Some(());
 	}
 
-	fn jif(&mut self, mode1: i64, mode2: i64) {
-		let o1 = self.load(self.pc+1, mode1);
-		let o2 = self.load(self.pc+2, mode2);
+	fn jif(&mut self, mode1: i64, mode2: i64) -> Option<()> {
+		let o1 = self.load(self.pc+1, mode1)?;
+		let o2 = self.load(self.pc+2, mode2)?;
 		// println!("jif {o1}:{mode1}, {o2}:{mode2}");
 
 		if o1 == 0 {
-			self.pc = usize::try_from(o2).unwrap();
+			self.pc = usize::try_from(o2).ok()?;
 		} else {
 			self.pc += 3;
 		}
+		return Some(());
 	}
 
-	fn lt(&mut self, mode1: i64, mode2: i64) {
-		let o1 = self.load(self.pc+1, mode1);
-		let o2 = self.load(self.pc+2, mode2);
-		let o3 = self.load(self.pc+3, 1);	// writes always use direct
+	fn lt(&mut self, mode1: i64, mode2: i64, mode3: i64) -> Option<()> {
+		let o1 = self.load(self.pc+1, mode1)?;
+		let o2 = self.load(self.pc+2, mode2)?;
+		let o3 = self.target(self.pc+3, mode3)?;
 		// println!("lt {o1}:{mode1} < {o2}:{mode2} => {o3}");
 
 		if o1 < o2 {
-			self.store(usize::try_from(o3).unwrap(), 1);
+			self.store(o3, 1)?;
 		} else {
-			self.store(usize::try_from(o3).unwrap(), 0);
+			self.store(o3, 0)?;
 		}
 		
 		self.pc += 4;
+		return Some(());
 	}
 	
-	fn eq(&mut self, mode1: i64, mode2: i64) {
-		let o1 = self.load(self.pc+1, mode1);
-		let o2 = self.load(self.pc+2, mode2);
-		let o3 = self.load(self.pc+3, 1);	// writes always use direct
+	fn eq(&mut self, mode1: i64, mode2: i64, mode3: i64) -> Option<()> {
+		let o1 = self.load(self.pc+1, mode1)?;
+		let o2 = self.load(self.pc+2, mode2)?;
+		let o3 = self.target(self.pc+3, mode3)?;
 		// println!("eq {o1}:{mode1} == {o2}:{mode2} => {o3}");
 
 		if o1 == o2 {
-			self.store(usize::try_from(o3).unwrap(), 1);
+			self.store(o3, 1)?;
 		} else {
-			self.store(usize::try_from(o3).unwrap(), 0);
+			self.store(o3, 0)?;
 		}
 		
 		self.pc += 4;
+		return Some(());
 	}
 
-	fn term(&mut self) {
+	fn term(&mut self) -> Option<()> {
 		// println!("term");
 		self.pc = self.memory.len();
+		return Some(());
 	}
 
-	fn step(&mut self) {
-		let pc = self.pc;
+	fn is_terminated(&self) -> bool {
+		return self.fault || self.pc >= self.memory.len();
+	}
+
+	// run one instruction, false once halted, faulted or waiting on input
+	fn step(&mut self) -> bool {
+		if self.is_terminated() {
+			return false;
+		}
 
 		let instruction = self.memory[self.pc];
 		let op = instruction % 100;
 		let m1 = (instruction / 100) % 10;
 		let m2 = (instruction / 1000) % 10;
-		// let m3 = (instruction / 10000) % 10;
-
-		if self.pc < self.memory.len() {
-			match op {
-				1	=> self.add(m1, m2),
-				2	=> self.mul(m1, m2),
-				3	=> self.inp(m1),
-				4	=> self.out(m1),
-				5	=> self.jit(m1, m2),
-				6	=> self.jif(m1, m2),
-				7	=> self.lt(m1, m2),
-				8	=> self.eq(m1, m2),
-				99 	=> self.term(),
-				n 	=> println!("{pc}: unknown operation {n}")
-			}
+		let m3 = (instruction / 10000) % 10;
+
+		let result = match op {
+			1	=> self.add(m1, m2, m3),
+			2	=> self.mul(m1, m2, m3),
+			3	=> {
+				if self.input.is_empty() {
+					return false;
+				}
+				self.inp(m1)
+			},
+			4	=> self.out(m1),
+			5	=> self.jit(m1, m2),
+			6	=> self.jif(m1, m2),
+			7	=> self.lt(m1, m2, m3),
+			8	=> self.eq(m1, m2, m3),
+			99 	=> self.term(),
+			_ 	=> None,
+		};
+
+		if result.is_none() {
+			// println!("{pc}: bad instruction {instruction}");
+			self.fault = true;
 		}
+
+		return result.is_some();
 	}
 
 	fn run(&mut self) -> i64 {
 		// self.show();
-		while self.pc < self.memory.len() {
-			self.step();
+		while self.step() {
 			// self.show();
 		}
 
-		return self.memory[0];
+		return self.memory.first().copied().unwrap_or(0);
 	}
 
 	#[allow(dead_code)]
@@ -188,12 +222,13 @@ impl CPU {
 	}
 }
 
-fn build_cpu(program: &Vec<i64>, input: &Vec<i64>) -> CPU {
+fn build_cpu(program: &[i64], input: &[i64]) -> CPU {
 	return CPU {
 		pc: 0,
-		memory: program.clone(),
-		input: VecDeque::from_iter(input.clone()),
+		memory: program.to_vec(),
+		input: VecDeque::from_iter(input.iter().copied()),
 		output: VecDeque::new(),
+		fault: false,
 	};
 }
 
@@ -231,6 +266,23 @@ fn part2(program: &Vec<i64>) -> i64 {
 	};
 }
 
+// iterations and seed for --fuzz, a seed from the clock if none is given
+fn read_fuzz_options(args: &[String]) -> Result<(usize, u64), String> {
+	let iterations = match args.first() {
+		Some(n) => n.parse().map_err(|_| format!("Bad iteration count {n}"))?,
+		None => 10_000,
+	};
+	let seed = match args.get(1) {
+		Some(n) => n.parse().map_err(|_| format!("Bad seed {n}"))?,
+		None => SystemTime::now().duration_since(UNIX_EPOCH).map_or(1, |time| time.as_nanos() as u64),
+	};
+	if args.len() > 2 {
+		return Err(format!("Unknown option {}", args[2]));
+	}
+
+	return Ok((iterations, seed));
+}
+
 fn main() {
 	let args: Vec<String> = env::args().collect();
 	if args.len() < 2 {
@@ -238,6 +290,19 @@ fn main() {
 		process::exit(1);
 	}
 
+	// day05 --fuzz [iterations] [seed]
+	if args[1] == "--fuzz" {
+		let (iterations, seed) = read_fuzz_options(&args[2..]).unwrap_or_else(|error| {
+			println!("ERROR: {error}");
+			process::exit(1);
+		});
+
+		println!("fuzz: seed {seed}");
+		let report = fuzz::fuzz(iterations, seed);
+		report.show();
+		process::exit(if report.passed() { 0 } else { 1 });
+	}
+
 	let start = Instant::now();
 
 	let data = read_data(&args[1]);
@@ -0,0 +1,826 @@
+use std::fs;
+use std::io;
+use std::collections::HashMap;
+
+// Minimal RGB image with PPM, PBM, PNG and animated GIF writers (and PPM,
+// PBM and PNG readers), so pictures can be saved and loaded without any
+// image crates. PNG data is written uncompressed.
+
+pub type Rgb = [u8; 3];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Rgb>,           // row by row from the top left
+    pub alpha: Option<Vec<u8>>,     // opacity of each pixel, None if opaque
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize, background: Rgb) -> Self {
+        return Image { width, height, pixels: vec![background; width * height], alpha: None };
+    }
+
+    // opacity of a pixel, 0 transparent to 255 opaque
+    #[allow(dead_code)]
+    pub fn opacity(&self, x: usize, y: usize) -> u8 {
+        return match &self.alpha {
+            Some(alpha) if x < self.width && y < self.height => alpha[y * self.width + x],
+            _ => 255,
+        };
+    }
+
+    #[allow(dead_code)]
+    pub fn set_opacity(&mut self, x: usize, y: usize, opacity: u8) {
+        if x < self.width && y < self.height {
+            let alpha = self.alpha.get_or_insert_with(|| vec![255; self.pixels.len()]);
+            alpha[y * self.width + x] = opacity;
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Option<Rgb> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        return Some(self.pixels[y * self.width + x]);
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, colour: Rgb) {
+        if x < self.width && y < self.height {
+            self.pixels[y * self.width + x] = colour;
+        }
+    }
+
+    // fill a size x size block, for drawing scaled up tiles
+    pub fn fill(&mut self, x: usize, y: usize, size: usize, colour: Rgb) {
+        for dy in 0..size {
+            for dx in 0..size {
+                self.set(x * size + dx, y * size + dy, colour);
+            }
+        }
+    }
+
+    pub fn ppm(&self) -> Vec<u8> {
+        let mut bytes = format!("P6\n{} {}\n255\n", self.width, self.height).into_bytes();
+        bytes.extend(self.pixels.iter().flatten());
+        return bytes;
+    }
+
+    // black and white, pixels darker than mid grey are black. PBM has no
+    // transparency, mostly transparent pixels are white
+    pub fn pbm(&self) -> Vec<u8> {
+        let mut bytes = format!("P4\n{} {}\n", self.width, self.height).into_bytes();
+        for y in 0..self.height {
+            // 8 pixels a byte, most significant bit first, rows padded
+            for x0 in (0..self.width).step_by(8) {
+                let mut byte = 0u8;
+                for x in x0..(x0 + 8).min(self.width) {
+                    if self.opacity(x, y) >= 128 && luma(self.pixels[y * self.width + x]) < 128 {
+                        byte |= 0x80 >> (x - x0);
+                    }
+                }
+                bytes.push(byte);
+            }
+        }
+        return bytes;
+    }
+
+    pub fn png(&self) -> Vec<u8> {
+        let mut header = Vec::new();
+        header.extend((self.width as u32).to_be_bytes());
+        header.extend((self.height as u32).to_be_bytes());
+        // 8 bit RGB, or RGBA with alpha, no interlace
+        header.extend([8, if self.alpha.is_some() { 6 } else { 2 }, 0, 0, 0]);
+
+        // every row starts with filter type 0 (none)
+        let mut raw = Vec::with_capacity((self.width * 4 + 1) * self.height);
+        for y in 0..self.height {
+            raw.push(0);
+            for x in 0..self.width {
+                raw.extend(self.pixels[y * self.width + x]);
+                if self.alpha.is_some() {
+                    raw.push(self.opacity(x, y));
+                }
+            }
+        }
+
+        let mut bytes = vec![0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+        chunk(&mut bytes, b"IHDR", &header);
+        chunk(&mut bytes, b"IDAT", &zlib_stored(&raw));
+        chunk(&mut bytes, b"IEND", &[]);
+        return bytes;
+    }
+
+    // write as PNG, PPM or PBM depending on the file extension
+    pub fn save(&self, filename: &str) -> io::Result<()> {
+        if filename.ends_with(".ppm") {
+            return fs::write(filename, self.ppm());
+        }
+        if filename.ends_with(".pbm") {
+            return fs::write(filename, self.pbm());
+        }
+        return fs::write(filename, self.png());
+    }
+}
+
+// brightness 0 to 255
+pub fn luma([r, g, b]: Rgb) -> u32 {
+    return (r as u32 * 299 + g as u32 * 587 + b as u32 * 114) / 1000;
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xffffffffu32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xedb88320 } else { crc >> 1 };
+        }
+    }
+    return !crc;
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in bytes {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    return (b << 16) | a;
+}
+
+// length, type, data, CRC of type and data
+fn chunk(bytes: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    bytes.extend((data.len() as u32).to_be_bytes());
+    let start = bytes.len();
+    bytes.extend(kind);
+    bytes.extend(data);
+    let crc = crc32(&bytes[start..]);
+    bytes.extend(crc.to_be_bytes());
+}
+
+// zlib stream made of uncompressed deflate blocks
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut bytes = vec![0x78, 0x01];
+    let blocks: Vec<&[u8]> = if data.is_empty() { vec![data] } else { data.chunks(0xffff).collect() };
+
+    for (i, block) in blocks.iter().enumerate() {
+        let last = i + 1 == blocks.len();
+        let length = block.len() as u16;
+        bytes.push(last as u8);
+        bytes.extend(length.to_le_bytes());
+        bytes.extend((!length).to_le_bytes());
+        bytes.extend(*block);
+    }
+
+    bytes.extend(adler32(data).to_be_bytes());
+    return bytes;
+}
+
+// Reading
+
+impl Image {
+    // read a PNG, PPM (P6) or PBM (P1 or P4) file
+    #[allow(dead_code)]
+    pub fn load(filename: &str) -> Result<Image, String> {
+        let bytes = fs::read(filename).map_err(|error| format!("{filename}: {error}"))?;
+        return Image::decode(&bytes).map_err(|error| format!("{filename}: {error}"));
+    }
+
+    // the format is told by the first bytes, not the file name
+    #[allow(dead_code)]
+    pub fn decode(bytes: &[u8]) -> Result<Image, String> {
+        return match bytes {
+            [0x89, b'P', b'N', b'G', ..] => read_png(bytes),
+            [b'P', b'1' | b'4' | b'6', ..] => read_pnm(bytes),
+            _ => Err("not a PNG, PPM or PBM image".to_string()),
+        };
+    }
+}
+
+// P1 (text PBM), P4 (binary PBM) or P6 (binary PPM) with 8 bit samples
+fn read_pnm(bytes: &[u8]) -> Result<Image, String> {
+    let kind = bytes[1];
+    let fields = if kind == b'6' { 3 } else { 2 };
+
+    // header numbers, separated by whitespace and # comments
+    let mut i = 2;
+    let mut header = Vec::new();
+    while header.len() < fields {
+        match bytes.get(i) {
+            Some(b'#') => while bytes.get(i).is_some_and(|&b| b != b'\n') { i += 1 },
+            Some(b) if b.is_ascii_whitespace() => i += 1,
+            Some(b) if b.is_ascii_digit() => {
+                let start = i;
+                while bytes.get(i).is_some_and(|b| b.is_ascii_digit()) { i += 1 }
+                let text = std::str::from_utf8(&bytes[start..i]).expect("digits");
+                header.push(text.parse::<usize>().map_err(|_| format!("bad number {text}"))?);
+            },
+            _ => return Err("bad header".to_string()),
+        }
+    }
+    // one whitespace byte before binary data
+    i += 1;
+
+    let (width, height) = (header[0], header[1]);
+    let short = || "image data too short".to_string();
+    let too_big = || format!("{width}x{height} is too big");
+
+    // the data the header promises has to be there before making the image.
+    // P1 has a digit (and maybe whitespace) a pixel, starting right after
+    // the header
+    let needed = match kind {
+        b'1' => width.checked_mul(height),
+        b'4' => width.div_ceil(8).checked_mul(height),
+        _ => width.checked_mul(height).and_then(|pixels| pixels.checked_mul(3)),
+    }.ok_or_else(too_big)?;
+    if bytes.len() - (i - 1) < needed {
+        return Err(short());
+    }
+    let mut image = Image::new(width, height, [255, 255, 255]);
+
+    match kind {
+        b'1' => {
+            let bits = bytes[i - 1..].iter().filter(|b| !b.is_ascii_whitespace());
+            let mut count = 0;
+            for (pixel, &bit) in image.pixels.iter_mut().zip(bits) {
+                *pixel = if bit == b'1' { [0, 0, 0] } else { [255, 255, 255] };
+                count += 1;
+            }
+            if count < width * height {
+                return Err(short());
+            }
+        },
+        b'4' => {
+            let stride = width.div_ceil(8);
+            let data = bytes.get(i..i + stride * height).ok_or_else(short)?;
+            for y in 0..height {
+                for x in 0..width {
+                    if data[y * stride + x / 8] & (0x80 >> (x % 8)) != 0 {
+                        image.set(x, y, [0, 0, 0]);
+                    }
+                }
+            }
+        },
+        _ => {
+            if header[2] != 255 {
+                return Err(format!("unsupported maximum value {}", header[2]));
+            }
+            let data = bytes.get(i..i + width * height * 3).ok_or_else(short)?;
+            for (pixel, rgb) in image.pixels.iter_mut().zip(data.chunks(3)) {
+                *pixel = [rgb[0], rgb[1], rgb[2]];
+            }
+        },
+    }
+
+    return Ok(image);
+}
+
+// any non interlaced PNG: grey, RGB, palette, grey with alpha or RGBA at
+// any bit depth, transparency from an alpha channel or tRNS
+fn read_png(bytes: &[u8]) -> Result<Image, String> {
+    if bytes.len() < 8 || bytes[..8] != [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'] {
+        return Err("not a PNG".to_string());
+    }
+
+    let mut header = None;
+    let mut palette: Vec<Rgb> = Vec::new();
+    let mut transparency: Vec<u8> = Vec::new();
+    let mut compressed = Vec::new();
+
+    let mut i = 8;
+    while i + 12 <= bytes.len() {
+        let length = u32::from_be_bytes(bytes[i..i + 4].try_into().expect("4 bytes")) as usize;
+        let kind = &bytes[i + 4..i + 8];
+        let data = bytes.get(i + 8..i + 8 + length).ok_or("truncated chunk")?;
+        let crc = bytes.get(i + 8 + length..i + 12 + length).ok_or("truncated chunk")?;
+        if crc32(&bytes[i + 4..i + 8 + length]).to_be_bytes() != crc {
+            return Err(format!("bad CRC in {} chunk", String::from_utf8_lossy(kind)));
+        }
+
+        match kind {
+            b"IHDR" if length == 13 => header = Some(data.to_vec()),
+            b"PLTE" => palette = data.chunks(3).filter(|c| c.len() == 3).map(|c| [c[0], c[1], c[2]]).collect(),
+            b"tRNS" => transparency = data.to_vec(),
+            b"IDAT" => compressed.extend(data),
+            b"IEND" => break,
+            _ => {},
+        }
+        i += 12 + length;
+    }
+
+    let header = header.ok_or("no IHDR chunk")?;
+    let width = u32::from_be_bytes(header[0..4].try_into().expect("4 bytes")) as usize;
+    let height = u32::from_be_bytes(header[4..8].try_into().expect("4 bytes")) as usize;
+    let (depth, colour, interlace) = (header[8] as usize, header[9], header[12]);
+
+    if interlace != 0 {
+        return Err("interlaced PNGs are not supported".to_string());
+    }
+    let channels = match (colour, depth) {
+        (0, 1 | 2 | 4 | 8 | 16) => 1,
+        (3, 1 | 2 | 4 | 8) => 1,
+        (2 | 4 | 6, 8 | 16) => [0, 0, 3, 0, 2, 0, 4][colour as usize],
+        _ => return Err(format!("unsupported colour type {colour} at bit depth {depth}")),
+    };
+
+    // a row is a filter byte and the packed samples. The sizes come from
+    // the header, so check them before inflating or making the image
+    let bits = channels * depth;
+    let too_big = || format!("{width}x{height} is too big");
+    let stride = width.checked_mul(bits).ok_or_else(too_big)?.div_ceil(8);
+    let needed = stride.checked_add(1).and_then(|row| row.checked_mul(height)).ok_or_else(too_big)?;
+    if width.checked_mul(height).is_none() {
+        return Err(too_big());
+    }
+
+    // undo the row filters
+    let step = bits.div_ceil(8);   // bytes between a byte and the one to its left
+    let raw = zlib_inflate(&compressed)?;
+    if raw.len() < needed {
+        return Err("image data too short".to_string());
+    }
+
+    let mut rows: Vec<Vec<u8>> = Vec::with_capacity(height);
+    for y in 0..height {
+        let line = &raw[y * (stride + 1)..(y + 1) * (stride + 1)];
+        let mut row = line[1..].to_vec();
+        for x in 0..stride {
+            let a = if x >= step { row[x - step] as i32 } else { 0 };
+            let b = if y > 0 { rows[y - 1][x] as i32 } else { 0 };
+            let c = if y > 0 && x >= step { rows[y - 1][x - step] as i32 } else { 0 };
+            let predicted = match line[0] {
+                0 => 0,
+                1 => a,
+                2 => b,
+                3 => (a + b) / 2,
+                4 => {
+                    let p = a + b - c;
+                    let (pa, pb, pc) = ((p - a).abs(), (p - b).abs(), (p - c).abs());
+                    if pa <= pb && pa <= pc { a } else if pb <= pc { b } else { c }
+                },
+                filter => return Err(format!("bad filter {filter}")),
+            };
+            row[x] = row[x].wrapping_add(predicted as u8);
+        }
+        rows.push(row);
+    }
+
+    // sample n of a row, as read (not scaled)
+    let sample = |row: &[u8], n: usize| -> u16 {
+        return match depth {
+            16 => u16::from_be_bytes([row[n * 2], row[n * 2 + 1]]),
+            8 => row[n] as u16,
+            _ => {
+                let bit = n * depth;
+                ((row[bit / 8] >> (8 - depth - bit % 8)) & ((1 << depth) - 1) as u8) as u16
+            },
+        };
+    };
+    let max = ((1u32 << depth) - 1) as u16;
+    let scale = |value: u16| (value as u32 * 255 / max as u32) as u8;
+    let key = |n: usize| transparency.get(n * 2..n * 2 + 2).map(|b| u16::from_be_bytes([b[0], b[1]]));
+
+    let mut image = Image::new(width, height, [0, 0, 0]);
+    for (y, row) in rows.iter().enumerate() {
+        for x in 0..width {
+            let s = |c: usize| sample(row, x * channels + c);
+            let (rgb, opacity) = match colour {
+                0 => {
+                    let grey = scale(s(0));
+                    ([grey; 3], if key(0) == Some(s(0)) { 0 } else { 255 })
+                },
+                2 => {
+                    let rgb = [scale(s(0)), scale(s(1)), scale(s(2))];
+                    let keyed = (0..3).all(|c| key(c) == Some(s(c)));
+                    (rgb, if keyed { 0 } else { 255 })
+                },
+                3 => {
+                    let index = s(0) as usize;
+                    let rgb = *palette.get(index).ok_or(format!("colour {index} not in the palette"))?;
+                    (rgb, transparency.get(index).copied().unwrap_or(255))
+                },
+                4 => ([scale(s(0)); 3], scale(s(1))),
+                _ => ([scale(s(0)), scale(s(1)), scale(s(2))], scale(s(3))),
+            };
+
+            image.set(x, y, rgb);
+            if opacity != 255 || image.alpha.is_some() || matches!(colour, 4 | 6) {
+                image.set_opacity(x, y, opacity);
+            }
+        }
+    }
+
+    return Ok(image);
+}
+
+// reads bits least significant first, the way deflate packs them
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    position: usize,    // in bits
+}
+
+impl BitReader<'_> {
+    fn bits(&mut self, count: u32) -> Result<u32, String> {
+        let mut value = 0;
+        for i in 0..count {
+            let byte = self.bytes.get(self.position / 8).ok_or("compressed data too short")?;
+            value |= (((byte >> (self.position % 8)) & 1) as u32) << i;
+            self.position += 1;
+        }
+        return Ok(value);
+    }
+
+    fn align(&mut self) {
+        self.position = self.position.div_ceil(8) * 8;
+    }
+}
+
+// canonical Huffman code given the code length of each symbol
+struct Huffman {
+    counts: [u16; 16],      // codes of each length
+    symbols: Vec<u16>,      // ordered by code
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0u16; 16];
+        lengths.iter().for_each(|&length| counts[length as usize] += 1);
+        counts[0] = 0;
+
+        let mut symbols: Vec<u16> = (0..lengths.len() as u16).filter(|&s| lengths[s as usize] > 0).collect();
+        symbols.sort_by_key(|&s| lengths[s as usize]);
+        return Huffman { counts, symbols };
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u16, String> {
+        // codes of each length follow on from the shorter ones
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for length in 1..16 {
+            code |= reader.bits(1)? as i32;
+            let count = self.counts[length] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        return Err("bad Huffman code".to_string());
+    }
+}
+
+const LENGTH_BASE: [u16; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DISTANCE_BASE: [u16; 30] = [1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577];
+const DISTANCE_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+
+// order code length code lengths are sent in
+const CODE_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+fn zlib_inflate(bytes: &[u8]) -> Result<Vec<u8>, String> {
+    if bytes.len() < 6 || bytes[0] & 0x0f != 8 || !(bytes[0] as u16 * 256 + bytes[1] as u16).is_multiple_of(31) {
+        return Err("bad zlib header".to_string());
+    }
+    if bytes[1] & 0x20 != 0 {
+        return Err("zlib preset dictionaries are not supported".to_string());
+    }
+
+    let mut reader = BitReader { bytes: &bytes[2..], position: 0 };
+    let data = inflate(&mut reader)?;
+
+    reader.align();
+    let end = 2 + reader.position / 8;
+    let checksum = bytes.get(end..end + 4).ok_or("no zlib checksum")?;
+    if adler32(&data).to_be_bytes() != checksum {
+        return Err("bad zlib checksum".to_string());
+    }
+
+    return Ok(data);
+}
+
+fn inflate(reader: &mut BitReader) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+
+    loop {
+        let last = reader.bits(1)? == 1;
+        match reader.bits(2)? {
+            0 => {
+                reader.align();
+                let length = reader.bits(16)?;
+                let inverse = reader.bits(16)?;
+                if length != !inverse & 0xffff {
+                    return Err("bad stored block length".to_string());
+                }
+                for _ in 0..length {
+                    out.push(reader.bits(8)? as u8);
+                }
+            },
+            1 => {
+                let mut lengths = [8u8; 288];
+                lengths[144..256].fill(9);
+                lengths[256..280].fill(7);
+                inflate_block(reader, &mut out, &Huffman::new(&lengths), &Huffman::new(&[5; 30]))?;
+            },
+            2 => {
+                let literals = reader.bits(5)? as usize + 257;
+                let distances = reader.bits(5)? as usize + 1;
+                let codes = reader.bits(4)? as usize + 4;
+
+                let mut code_lengths = [0u8; 19];
+                for &i in &CODE_ORDER[..codes] {
+                    code_lengths[i] = reader.bits(3)? as u8;
+                }
+                let code = Huffman::new(&code_lengths);
+
+                let mut lengths = Vec::with_capacity(literals + distances);
+                while lengths.len() < literals + distances {
+                    let (value, repeat) = match code.decode(reader)? {
+                        symbol @ 0..=15 => (symbol as u8, 1),
+                        16 => (*lengths.last().ok_or("repeat with no length before it")?, 3 + reader.bits(2)?),
+                        17 => (0, 3 + reader.bits(3)?),
+                        _ => (0, 11 + reader.bits(7)?),
+                    };
+                    lengths.extend(std::iter::repeat_n(value, repeat as usize));
+                }
+                if lengths.len() > literals + distances {
+                    return Err("code lengths overrun".to_string());
+                }
+
+                let literal = Huffman::new(&lengths[..literals]);
+                let distance = Huffman::new(&lengths[literals..]);
+                inflate_block(reader, &mut out, &literal, &distance)?;
+            },
+            _ => return Err("bad block type".to_string()),
+        }
+
+        if last {
+            return Ok(out);
+        }
+    }
+}
+
+fn inflate_block(reader: &mut BitReader, out: &mut Vec<u8>, literal: &Huffman, distance: &Huffman) -> Result<(), String> {
+    loop {
+        let symbol = literal.decode(reader)? as usize;
+        if symbol < 256 {
+            out.push(symbol as u8);
+            continue;
+        }
+        if symbol == 256 {
+            return Ok(());
+        }
+
+        let i = symbol - 257;
+        if i >= LENGTH_BASE.len() {
+            return Err(format!("bad length code {symbol}"));
+        }
+        let length = LENGTH_BASE[i] as usize + reader.bits(LENGTH_EXTRA[i] as u32)? as usize;
+
+        let d = distance.decode(reader)? as usize;
+        if d >= DISTANCE_BASE.len() {
+            return Err(format!("bad distance code {d}"));
+        }
+        let back = DISTANCE_BASE[d] as usize + reader.bits(DISTANCE_EXTRA[d] as u32)? as usize;
+        if back > out.len() {
+            return Err("distance back past the start".to_string());
+        }
+
+        // may overlap what it is copying
+        let start = out.len() - back;
+        for k in 0..length {
+            out.push(out[start + k]);
+        }
+    }
+}
+
+// Animated GIF built one frame at a time. Colours not in the palette are
+// drawn with the first palette entry.
+#[allow(dead_code)]
+pub struct Gif {
+    width: usize,
+    height: usize,
+    palette: Vec<Rgb>,
+    delay: u16,         // per frame, in 1/100 s
+    frames: Vec<u8>,    // encoded frames so far
+}
+
+#[allow(dead_code)]
+impl Gif {
+    // GIF sizes are 16 bit, bigger is an error
+    pub fn new(width: usize, height: usize, palette: &[Rgb], delay: u16) -> io::Result<Self> {
+        if width > u16::MAX as usize || height > u16::MAX as usize {
+            let message = format!("{width}x{height} is too big for a GIF, {0}x{0} at most", u16::MAX);
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+        }
+
+        let mut palette = palette.to_vec();
+        palette.truncate(256);
+        return Ok(Gif { width, height, palette, delay, frames: Vec::new() });
+    }
+
+    // bits per colour index, GIF needs at least 1 (LZW at least 2)
+    fn depth(&self) -> u32 {
+        let mut depth = 1;
+        while (1 << depth) < self.palette.len() {
+            depth += 1;
+        }
+        return depth;
+    }
+
+    // add a frame, cropped or padded to the size of the animation
+    pub fn add(&mut self, image: &Image) {
+        let index: HashMap<Rgb, u8> = self.palette.iter()
+            .enumerate()
+            .rev()
+            .map(|(i, &colour)| (colour, i as u8))
+            .collect();
+
+        let mut pixels = Vec::with_capacity(self.width * self.height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let colour = image.get(x, y);
+                pixels.push(colour.and_then(|c| index.get(&c).copied()).unwrap_or(0));
+            }
+        }
+
+        // graphic control extension with the frame delay
+        self.frames.extend([0x21, 0xf9, 4, 0]);
+        self.frames.extend(self.delay.to_le_bytes());
+        self.frames.extend([0, 0]);
+
+        // image descriptor covering the whole screen, no local palette
+        self.frames.push(0x2c);
+        self.frames.extend([0, 0, 0, 0]);
+        self.frames.extend((self.width as u16).to_le_bytes());
+        self.frames.extend((self.height as u16).to_le_bytes());
+        self.frames.push(0);
+
+        let minimum = self.depth().max(2);
+        self.frames.push(minimum as u8);
+        for block in lzw(&pixels, minimum).chunks(255) {
+            self.frames.push(block.len() as u8);
+            self.frames.extend(block);
+        }
+        self.frames.push(0);
+    }
+
+    pub fn bytes(&self) -> Vec<u8> {
+        let depth = self.depth();
+        let mut bytes = b"GIF89a".to_vec();
+        bytes.extend((self.width as u16).to_le_bytes());
+        bytes.extend((self.height as u16).to_le_bytes());
+        bytes.extend([0x80 | ((depth as u8 - 1) << 4) | (depth as u8 - 1), 0, 0]);
+
+        // global colour table, padded to a power of two
+        for i in 0..1 << depth {
+            bytes.extend(self.palette.get(i).unwrap_or(&[0, 0, 0]));
+        }
+
+        // loop forever
+        bytes.extend([0x21, 0xff, 11]);
+        bytes.extend(b"NETSCAPE2.0");
+        bytes.extend([3, 1, 0, 0, 0]);
+
+        bytes.extend(&self.frames);
+        bytes.push(0x3b);
+        return bytes;
+    }
+
+    pub fn save(&self, filename: &str) -> io::Result<()> {
+        return fs::write(filename, self.bytes());
+    }
+}
+
+// packs variable width codes least significant bit first
+#[allow(dead_code)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u32,
+    bits: u32,
+}
+
+#[allow(dead_code)]
+impl BitWriter {
+    fn write(&mut self, code: u32, width: u32) {
+        self.buffer |= code << self.bits;
+        self.bits += width;
+        while self.bits >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.bits -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bits > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+        return self.bytes;
+    }
+}
+
+// GIF flavoured LZW: codes grow from minimum + 1 to 12 bits and the table
+// starts over with a clear code when it is full
+#[allow(dead_code)]
+fn lzw(pixels: &[u8], minimum: u32) -> Vec<u8> {
+    let clear = 1u32 << minimum;
+    let end = clear + 1;
+
+    let mut out = BitWriter { bytes: Vec::new(), buffer: 0, bits: 0 };
+    let mut table: HashMap<(u32, u8), u32> = HashMap::new();
+    let mut next = end + 1;
+    let mut width = minimum + 1;
+
+    out.write(clear, width);
+
+    let Some((&first, rest)) = pixels.split_first() else {
+        out.write(end, width);
+        return out.finish();
+    };
+
+    let mut prefix = first as u32;
+    for &pixel in rest {
+        if let Some(&code) = table.get(&(prefix, pixel)) {
+            prefix = code;
+            continue;
+        }
+
+        out.write(prefix, width);
+        if next < 4096 {
+            table.insert((prefix, pixel), next);
+            if next == 1 << width {
+                width += 1;
+            }
+            next += 1;
+        } else {
+            out.write(clear, width);
+            table.clear();
+            next = end + 1;
+            width = minimum + 1;
+        }
+        prefix = pixel as u32;
+    }
+
+    out.write(prefix, width);
+    out.write(end, width);
+    return out.finish();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pnm_round_trip() {
+        let mut image = Image::new(10, 3, [255, 255, 255]);
+        image.set(0, 0, [0, 0, 0]);
+        image.set(9, 2, [0, 0, 0]);
+        let back = Image::decode(&image.pbm()).unwrap();
+        assert_eq!(back.pixels, image.pixels);
+
+        image.set(4, 1, [10, 20, 30]);
+        let back = Image::decode(&image.ppm()).unwrap();
+        assert_eq!(back.pixels, image.pixels);
+
+        let text = Image::decode(b"P1\n# comment\n3 2\n1 0 0\n0 0 1\n").unwrap();
+        assert_eq!(text.get(0, 0), Some([0, 0, 0]));
+        assert_eq!(text.get(1, 0), Some([255, 255, 255]));
+        assert_eq!(text.get(2, 1), Some([0, 0, 0]));
+    }
+
+    #[test]
+    fn pnm_size_is_checked_before_reading() {
+        let too_big = Err("99999999999999x99999999999999 is too big".to_string());
+        assert_eq!(Image::decode(b"P4\n99999999999999 99999999999999\n").map(|_| ()), too_big);
+        assert_eq!(Image::decode(b"P6\n99999999999999 99999999999999 255\n").map(|_| ()), too_big);
+
+        let short = Err("image data too short".to_string());
+        assert_eq!(Image::decode(b"P4\n9999999 9999999\n").map(|_| ()), short);
+        assert_eq!(Image::decode(b"P6\n100000 100000 255\n\x00\x00\x00").map(|_| ()), short);
+        assert_eq!(Image::decode(b"P1\n100000 100000\n1 0 1").map(|_| ()), short);
+        assert_eq!(Image::decode(b"P4\n9 2\n\x00\x00\x00").map(|_| ()), short);
+    }
+
+    // a PNG of the image with the size in the IHDR chunk replaced
+    fn png_sized(image: &Image, width: u32, height: u32) -> Vec<u8> {
+        let mut bytes = image.png();
+        bytes[16..20].copy_from_slice(&width.to_be_bytes());
+        bytes[20..24].copy_from_slice(&height.to_be_bytes());
+        let crc = crc32(&bytes[12..29]);
+        bytes[29..33].copy_from_slice(&crc.to_be_bytes());
+        return bytes;
+    }
+
+    #[test]
+    fn png_size_is_checked_before_reading() {
+        let image = Image::new(2, 2, [10, 20, 30]);
+        assert_eq!(Image::decode(&png_sized(&image, 2, 2)).unwrap().pixels, image.pixels);
+
+        let too_big = Err("4294967295x4294967295 is too big".to_string());
+        assert_eq!(Image::decode(&png_sized(&image, u32::MAX, u32::MAX)).map(|_| ()), too_big);
+
+        let short = Err("image data too short".to_string());
+        assert_eq!(Image::decode(&png_sized(&image, 100000, 100000)).map(|_| ()), short);
+        assert_eq!(Image::decode(&png_sized(&image, 3, 2)).map(|_| ()), short);
+    }
+}
@@ -3,87 +3,163 @@ use std::fs;
 use std::process;
 use std::time::Instant; // 0.8.2
 
-fn read_data(filename: &str) -> Vec<u32> {
-    fs::read_to_string(filename)
-        .expect("Expected input file to exist")
-        .replace("\n", "")
-        .chars()
-        // .map(|ch| u8::try_from(ch).unwrap())
-        .map(|ch| ch.to_digit(10).expect("Not a number"))
-        .collect()
-}
+mod image;
+mod ocr;
+mod sif;
+
+use image::Image;
+use sif::{Palette, Sif};
 
-fn digit_counts(raw: &[u32]) -> Vec<usize> {
-    let mut counts = vec![0; 10];
-    
-    for &digit in raw {
-        counts[digit as usize] += 1;
+// the puzzle's digits, or pictures to make the layers from given as
+// FRONT.png,NEXT.pbm,...
+fn read_data(filename: &str, options: &Options) -> Result<Sif, String> {
+    let pictures: Vec<&str> = filename.split(',').collect();
+    if pictures.iter().all(|name| [".png", ".pbm", ".ppm"].iter().any(|ext| name.ends_with(ext))) {
+        return Sif::import(&pictures, options.scale, &options.palette);
     }
 
-    return counts;
+    let text = fs::read_to_string(filename).expect("Expected input file to exist");
+    return Sif::parse(&text, options.width, options.height)
+        .map(|image| image.with_palette(options.palette.clone()))
+        .map_err(|error| format!("{filename}: {error}"));
 }
 
-fn part1(raw: &Vec<u32>) -> usize {
-    let (width, height) = match raw.len() {
-        12 => (3, 2),   // test.txt
-        16 => (2, 2),   // test-1.txt
-         _ => (25, 6),  // input.txt
-    };
-
-    let result = raw
-        // break into layers based on layer size
-        .chunks(width * height)
-        // get vector of the number of each digit
-        .map(|layer| digit_counts(layer))
+fn part1(image: &Sif) -> usize {
+    let result = image
+        // get vector of the number of each digit for each layer
+        .digit_counts()
+        .into_iter()
         // filter for the layer with the least 0s
         .min_by_key(|x| x[0])
         // transform to the product of 1s and 2s
         .map(|l| l[1] * l[2])
-        // unwrap Some() from min_by_key if collection was empty
+        // there is always at least one layer
         .unwrap();
 
     return result;
 }
 
-fn apply_layer(top: &Vec<u32>, bot: &[u32]) -> Vec<u32> {
-    return top.clone()
-        .into_iter()
-        .enumerate()
-        .map(|(i, s)| if s == 2 { bot[i] } else { s } )
-        .collect();
+fn show_picture(picture: &[u32], width: usize, palette: &Palette) {
+    for row in picture.chunks(width) {
+        for &digit in row {
+            print!("{}", palette.glyph(digit));
+        }
+        println!();
+    }
 }
 
-fn show_picture(picture: &[u32], width: usize) {
+// in colour, two terminal cells a pixel
+fn show_picture_ansi(picture: &[u32], width: usize, palette: &Palette) {
     for row in picture.chunks(width) {
-        for digit in row {
-            match digit {
-                0 => print!(" "),
-                1 => print!("*"),
-                _ => print!("_")
-            }
+        for &digit in row {
+            print!("{}", palette.ansi(digit));
         }
-        println!();
+        println!("\x1b[0m");
+    }
+}
+
+fn part2(image: &Sif, options: &Options) -> String {
+    let width = image.width;
+    export(image, options);
+
+    // fold the layers into the final picture
+    let picture = image.composite();
+
+    if options.ansi {
+        show_picture_ansi(&picture, width, &image.palette);
     }
+
+    // BCYEF for input, show the picture if it isn't all letters. The
+    // letters are bright, or dark on a bright background
+    let bright: Vec<bool> = picture.iter().map(|&digit| image.palette.is_bright(digit)).collect();
+    let dark: Vec<bool> = picture.iter().map(|&digit| image.palette.is_dark(digit)).collect();
+    return match ocr::read(&bright, width).or_else(|_| ocr::read(&dark, width)) {
+        Ok(text) => text,
+        Err(text) => {
+            if !options.ansi {
+                show_picture(&picture, width, &image.palette);
+            }
+            format!("{text:?}")
+        },
+    };
 }
 
-fn part2(raw: &Vec<u32>) -> usize {
-    let (width, height) = match raw.len() {
-        12 => (3, 2),   // test.txt
-        16 => (2, 2),   // test-1.txt
-         _ => (25, 6),  // input.txt
+// write out what the options ask for
+fn export(image: &Sif, options: &Options) {
+    let save = |picture: &Image, filename: &str| {
+        if let Err(error) = picture.save(filename) {
+            println!("ERROR: {filename}: {error}");
+        }
     };
 
-    let empty: Vec<u32> = vec![2; width * height];
+    if let Some(filename) = &options.export {
+        save(&image.image(&image.composite(), options.scale), filename);
+    }
+
+    if let Some((extension, prefix)) = &options.layers {
+        for (i, layer) in image.layers().enumerate() {
+            save(&image.image(layer, options.scale), &format!("{prefix}{i:03}.{extension}"));
+        }
+    }
 
-    let picture = raw
-        // break into layers vector of vec<32>
-        .chunks(width * height)
-        // fold into final picture by applying layers
-        .fold(empty, |pic, layer| apply_layer(&pic, layer));
+    if let Some(filename) = &options.encode && let Err(error) = fs::write(filename, image.encode()) {
+        println!("ERROR: {filename}: {error}");
+    }
+}
 
-    // BCYEF for input
-    show_picture(&picture, width);
-    return picture.len();
+struct Options {
+    width: usize,                           // --width PIXELS
+    height: usize,                          // --height PIXELS
+    scale: usize,                           // --scale N, picture pixels per image pixel
+    export: Option<String>,                 // --export FILE, the picture (.png, .ppm or .pbm)
+    layers: Option<(String, String)>,       // --layers png:PREFIX or pbm:PREFIX, every layer
+    encode: Option<String>,                 // --encode FILE, the digits
+    palette: Palette,                       // --palette DIGIT=RRGGBB|transparent|none,...
+    ansi: bool,                             // --ansi, show the picture in colour
+}
+
+// the puzzle's image size
+const WIDTH: usize = 25;
+const HEIGHT: usize = 6;
+
+// --width W and --height H for the tests (test.txt is 3x2, test-1.txt 2x2)
+fn read_options(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        width: WIDTH, height: HEIGHT, scale: 1, export: None, layers: None, encode: None,
+        palette: Palette::default(), ansi: false,
+    };
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--width" => {
+                let pixels = args.next().ok_or("--width needs pixels")?;
+                options.width = pixels.parse().map_err(|_| format!("Bad width {pixels}"))?;
+            },
+            "--height" => {
+                let pixels = args.next().ok_or("--height needs pixels")?;
+                options.height = pixels.parse().map_err(|_| format!("Bad height {pixels}"))?;
+            },
+            "--scale" => {
+                let pixels = args.next().ok_or("--scale needs pixels per image pixel")?;
+                options.scale = pixels.parse().ok().filter(|&n| n > 0).ok_or(format!("Bad scale {pixels}"))?;
+            },
+            "--export" => options.export = Some(args.next().ok_or("--export needs a file")?.clone()),
+            "--layers" => {
+                let layers = args.next().ok_or("--layers needs png:PREFIX or pbm:PREFIX")?;
+                match layers.split_once(':') {
+                    Some((kind @ ("png" | "pbm"), prefix)) => options.layers = Some((kind.to_string(), prefix.to_string())),
+                    _ => return Err(format!("Bad layers {layers}, expected png:PREFIX or pbm:PREFIX")),
+                }
+            },
+            "--encode" => options.encode = Some(args.next().ok_or("--encode needs a file")?.clone()),
+            "--palette" => options.palette.parse(args.next().ok_or("--palette needs DIGIT=RRGGBB,...")?)?,
+            "--ansi" => options.ansi = true,
+            _ => return Err(format!("Unknown option {arg}")),
+        }
+    }
+
+    return Ok(options);
 }
 
 fn main() {
@@ -93,9 +169,17 @@ fn main() {
         process::exit(1);
     }
 
+    let options = read_options(&args[2..]).unwrap_or_else(|error| {
+        println!("ERROR: {error}");
+        process::exit(1);
+    });
+
     let start = Instant::now();
 
-    let data = read_data(&args[1]);
+    let data = read_data(&args[1], &options).unwrap_or_else(|error| {
+        println!("ERROR: {error}");
+        process::exit(1);
+    });
     println!(
         "          parse ({:9.9} ns)",
         Instant::now().duration_since(start).as_nanos()
@@ -109,7 +193,7 @@ fn main() {
     );
 
     let p2_start = Instant::now();
-    let p2 = part2(&data);
+    let p2 = part2(&data, &options);
 
     println!(
         "{p2:>15} ({:9.9} ns)",
@@ -120,3 +204,83 @@ fn main() {
         Instant::now().duration_since(start).as_nanos()
     );
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // every conversion there and back again should give the same pixels, as
+    // far as the palette can tell them apart
+    fn check(image: &Sif, scale: usize) -> Result<(), String> {
+        let palette = &image.palette;
+        if let Some(digit) = image.layers().flatten().find(|&&digit| palette.canonical(digit).is_none()) {
+            return Err(format!("digit {digit} has no colour in the palette"));
+        }
+
+        let text = image.encode();
+        let parsed = Sif::parse(&text, image.width, image.height).map(|parsed| parsed.with_palette(palette.clone()));
+        if parsed.as_ref() != Ok(image) {
+            return Err("encoded digits read back differently".to_string());
+        }
+
+        let layers: Vec<Vec<u32>> = image.layers().map(|layer| layer.to_vec()).collect();
+        let composed = Sif::from_layers(&layers, image.width, image.height).map(|composed| composed.with_palette(palette.clone()));
+        if composed.as_ref() != Ok(image) {
+            return Err("layers put back together differently".to_string());
+        }
+
+        // digits with the same colour come back as the first of them. PBM is
+        // black and white only, transparent comes back white
+        let png_digit = |digit: u32| palette.canonical(digit);
+        let pbm_digit = |digit: u32| palette.digit(if palette.is_dark(digit) { [0, 0, 0] } else { [255, 255, 255] }, 255);
+
+        let there_and_back = |pixels: &[u32], pbm: bool| -> Result<bool, String> {
+            let picture = image.image(pixels, scale);
+            let bytes = if pbm { picture.pbm() } else { picture.png() };
+            let back = palette.pixels(&Image::decode(&bytes)?, scale)?;
+
+            let expected: Vec<Option<u32>> = pixels.iter()
+                .map(|&digit| if pbm { pbm_digit(digit) } else { png_digit(digit) })
+                .collect();
+            return Ok(back.into_iter().map(Some).eq(expected));
+        };
+
+        for i in 0..image.layer_count() {
+            let layer = image.layer(i).expect("layer in range");
+            if !there_and_back(layer, false)? {
+                return Err(format!("layer {i} read back from PNG differently"));
+            }
+            if !there_and_back(layer, true)? {
+                return Err(format!("layer {i} read back from PBM differently"));
+            }
+        }
+
+        let picture = image.composite();
+        if !there_and_back(&picture, false)? || !there_and_back(&picture, true)? {
+            return Err("composited picture read back differently".to_string());
+        }
+
+        return Ok(());
+    }
+
+    #[test]
+    fn puzzle_example_round_trips() {
+        let image = Sif::parse("0222112222120000", 2, 2).unwrap();
+        assert_eq!(check(&image, 1), Ok(()));
+        assert_eq!(check(&image, 3), Ok(()));
+    }
+
+    #[test]
+    fn palette_round_trips() {
+        let mut palette = Palette::default();
+        palette.parse("3=ff0000,4=ff0000,9=transparent").unwrap();
+        let image = Sif::parse("012349 943210 222222 000111", 3, 2).unwrap().with_palette(palette);
+        assert_eq!(check(&image, 2), Ok(()));
+    }
+
+    #[test]
+    fn digit_without_a_colour() {
+        let image = Sif::parse("0125", 2, 2).unwrap();
+        assert_eq!(check(&image, 1), Err("digit 5 has no colour in the palette".to_string()));
+    }
+}
@@ -0,0 +1,138 @@
+// Reads the capital letters the puzzles draw (day 8, day 11)
+//
+// Letters are 6 pixels high and sit on a 5 pixel wide grid: 4 columns for
+// the letter and a blank one, except Y which uses all 5. The picture may
+// have blank rows and columns around the text.
+
+const HEIGHT: usize = 6;
+const STRIDE: usize = 5;
+
+const FONT: [(char, [&str; HEIGHT]); 18] = [
+    ('A', [".##.", "#..#", "#..#", "####", "#..#", "#..#"]),
+    ('B', ["###.", "#..#", "###.", "#..#", "#..#", "###."]),
+    ('C', [".##.", "#..#", "#...", "#...", "#..#", ".##."]),
+    ('E', ["####", "#...", "###.", "#...", "#...", "####"]),
+    ('F', ["####", "#...", "###.", "#...", "#...", "#..."]),
+    ('G', [".##.", "#..#", "#...", "#.##", "#..#", ".###"]),
+    ('H', ["#..#", "#..#", "####", "#..#", "#..#", "#..#"]),
+    ('I', [".###", "..#.", "..#.", "..#.", "..#.", ".###"]),
+    ('J', ["..##", "...#", "...#", "...#", "#..#", ".##."]),
+    ('K', ["#..#", "#.#.", "##..", "#.#.", "#.#.", "#..#"]),
+    ('L', ["#...", "#...", "#...", "#...", "#...", "####"]),
+    ('O', [".##.", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('P', ["###.", "#..#", "#..#", "###.", "#...", "#..."]),
+    ('R', ["###.", "#..#", "#..#", "###.", "#.#.", "#..#"]),
+    ('S', [".###", "#...", "#...", ".##.", "...#", "###."]),
+    ('U', ["#..#", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('Y', ["#...#", "#...#", ".#.#.", "..#..", "..#..", "..#.."]),
+    ('Z', ["####", "...#", "..#.", ".#..", "#...", "####"]),
+];
+
+// the letter drawn in the 5 columns of rows starting at left
+fn glyph(rows: &[&[bool]], left: usize) -> Option<char> {
+    let lit = |row: &[bool], x: usize| row.get(x).copied().unwrap_or(false);
+
+    return FONT.iter()
+        .find(|(_letter, pattern)| {
+            rows.iter().zip(pattern).all(|(row, line)| {
+                let line = line.as_bytes();
+                (0..STRIDE).all(|x| lit(row, left + x) == (line.get(x) == Some(&b'#')))
+            })
+        })
+        .map(|(letter, _pattern)| *letter);
+}
+
+// What read makes of a picture: Ok with the text, or Err with as much of
+// it as could be read, '?' for each letter that couldn't (empty if there
+// is no line of text at all)
+pub type Reading = Result<String, String>;
+
+// read the text in a picture given row by row, width pixels per row
+pub fn read(pixels: &[bool], width: usize) -> Reading {
+    if width == 0 {
+        return Err(String::new());
+    }
+    let rows: Vec<&[bool]> = pixels.chunks(width)
+        .filter(|row| row.iter().any(|&pixel| pixel))
+        .collect();
+
+    if rows.len() != HEIGHT {
+        return Err(String::new());
+    }
+
+    let lit_columns: Vec<usize> = (0..width)
+        .filter(|&x| rows.iter().any(|row| row.get(x) == Some(&true)))
+        .collect();
+    let (first, last) = (lit_columns[0], lit_columns[lit_columns.len() - 1]);
+
+    // most letters start in their first column, I starts in its second
+    let mut best = String::new();
+    for left in [Some(first), first.checked_sub(1)].into_iter().flatten() {
+        let text: String = (left..=last)
+            .step_by(STRIDE)
+            .map(|x| glyph(&rows, x).unwrap_or('?'))
+            .collect();
+
+        if !text.contains('?') {
+            return Ok(text);
+        }
+        if best.is_empty() {
+            best = text;
+        }
+    }
+
+    return Err(best);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // text drawn in FONT with left blank columns before it and a blank row
+    // above and below, in a picture width pixels wide
+    fn draw(text: &str, left: usize, width: usize) -> Vec<bool> {
+        let mut pixels = vec![false; width * (HEIGHT + 2)];
+        for (n, letter) in text.chars().enumerate() {
+            let (_letter, pattern) = FONT.iter().find(|(l, _pattern)| *l == letter).unwrap();
+            for (y, line) in pattern.iter().enumerate() {
+                for (x, pixel) in line.bytes().enumerate() {
+                    let x = left + n * STRIDE + x;
+                    if x < width {
+                        pixels[(y + 1) * width + x] = pixel == b'#';
+                    }
+                }
+            }
+        }
+        return pixels;
+    }
+
+    #[test]
+    fn reads_known_letters() {
+        assert_eq!(read(&draw("BCYEF", 0, 25), 25), Ok("BCYEF".to_string()));
+        let every: String = FONT.iter().map(|(letter, _pattern)| *letter).collect();
+        let width = every.len() * STRIDE;
+        assert_eq!(read(&draw(&every, 0, width), width), Ok(every));
+    }
+
+    #[test]
+    fn unknown_letter_is_a_question_mark() {
+        let mut pixels = draw("HAZ", 0, 15);
+        pixels[3 * 15 + 6] = true;      // fill in the middle of the A
+        assert_eq!(read(&pixels, 15), Err("H?Z".to_string()));
+    }
+
+    #[test]
+    fn width_off_the_letter_grid() {
+        assert_eq!(read(&draw("HI", 3, 13), 13), Ok("HI".to_string()));
+        assert_eq!(read(&draw("IL", 2, 12), 12), Ok("IL".to_string()));
+        // the last letter is cut short by the edge of the picture
+        assert_eq!(read(&draw("LY", 0, 8), 8), Err("L?".to_string()));
+    }
+
+    #[test]
+    fn no_text() {
+        assert_eq!(read(&[false; 40], 8), Err(String::new()));
+        assert_eq!(read(&draw("E", 0, 5), 0), Err(String::new()));
+        assert_eq!(read(&[], 5), Err(String::new()));
+    }
+}
@@ -0,0 +1,354 @@
+use std::fmt;
+use crate::image::{self, Image, Rgb};
+
+// Space Image Format: digits in layers of width x height pixels, the first
+// layer in front. 0 is black, 1 is white and 2 is transparent unless a
+// palette says otherwise.
+
+pub const BLACK: u32 = 0;
+pub const WHITE: u32 = 1;
+pub const TRANSPARENT: u32 = 2;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SifError {
+    BadSize(usize, usize),      // width or height of 0
+    BadDigit(usize, char),      // position in the text, character
+    BadValue(usize, u32),       // position in the data, value over 9
+    Empty,                      // no layers at all
+    BadLength(usize, usize),    // data length, layer size it isn't a multiple of
+    LayerSize(usize, usize, usize), // layer, its width and height, when composing
+}
+
+impl fmt::Display for SifError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            SifError::BadSize(w, h) => write!(f, "bad image size {w}x{h}"),
+            SifError::BadDigit(i, ch) => write!(f, "bad digit {ch:?} at {i}"),
+            SifError::BadValue(i, value) => write!(f, "pixel {i} is {value}, not a digit"),
+            SifError::Empty => write!(f, "no image data"),
+            SifError::BadLength(length, layer) => {
+                write!(f, "{length} digits is not a whole number of {layer} digit layers ({} left over)", length % layer)
+            },
+            SifError::LayerSize(i, w, h) => write!(f, "layer {i} is {w}x{h}, not the size of the first layer"),
+        };
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Sif {
+    pub width: usize,
+    pub height: usize,
+    pub palette: Palette,
+    data: Vec<u32>,     // every layer one after the other
+}
+
+// how many of each digit there are, an error for a value that isn't one
+pub fn digit_counts(raw: &[u32]) -> Result<Vec<usize>, SifError> {
+    let mut counts = vec![0; 10];
+
+    for (i, &digit) in raw.iter().enumerate() {
+        let count = counts.get_mut(digit as usize).ok_or(SifError::BadValue(i, digit))?;
+        *count += 1;
+    }
+
+    return Ok(counts);
+}
+
+// the top layer with its transparent pixels filled in from the bottom one
+pub fn apply_layer(top: &[u32], bot: &[u32], palette: &Palette) -> Vec<u32> {
+    return top.iter()
+        .zip(bot)
+        .map(|(&t, &b)| if palette.colour(t) == Colour::Transparent { b } else { t })
+        .collect();
+}
+
+// What a digit looks like
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Colour {
+    Rgb(Rgb),
+    Transparent,
+    Unset,          // digit with no colour
+}
+
+const BLACK_RGB: Rgb = [0, 0, 0];
+const WHITE_RGB: Rgb = [255, 255, 255];
+
+// drawn for pixels with no colour
+const UNSET_RGB: Rgb = [128, 128, 128];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Palette {
+    colours: [Colour; 10],
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        let mut colours = [Colour::Unset; 10];
+        colours[BLACK as usize] = Colour::Rgb(BLACK_RGB);
+        colours[WHITE as usize] = Colour::Rgb(WHITE_RGB);
+        colours[TRANSPARENT as usize] = Colour::Transparent;
+        return Palette { colours };
+    }
+}
+
+impl Palette {
+    pub fn colour(&self, digit: u32) -> Colour {
+        return self.colours.get(digit as usize).copied().unwrap_or(Colour::Unset);
+    }
+
+    // change colours with "DIGIT=RRGGBB,...", a colour can also be
+    // "transparent" or "none", e.g. "0=000000,1=ffffff,2=transparent,3=ff0000"
+    pub fn parse(&mut self, text: &str) -> Result<(), String> {
+        for entry in text.split(',') {
+            let bad = || format!("Bad palette entry {entry}, expected DIGIT=RRGGBB, DIGIT=transparent or DIGIT=none");
+            let (digit, colour) = entry.split_once('=').ok_or_else(bad)?;
+            let digit: usize = digit.trim().parse().ok().filter(|&d| d < 10).ok_or_else(bad)?;
+
+            self.colours[digit] = match colour.trim() {
+                "transparent" => Colour::Transparent,
+                "none" => Colour::Unset,
+                hex if hex.len() == 6 => {
+                    let rgb = u32::from_str_radix(hex, 16).map_err(|_| bad())?;
+                    Colour::Rgb([(rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8])
+                },
+                _ => return Err(bad()),
+            };
+        }
+
+        return Ok(());
+    }
+
+    // the digit a pixel reads back as: the first transparent digit if it is
+    // mostly transparent, otherwise the digit with the closest colour
+    pub fn digit(&self, rgb: Rgb, opacity: u8) -> Option<u32> {
+        let transparent = self.colours.iter().position(|&c| c == Colour::Transparent);
+        if opacity < 128 && transparent.is_some() {
+            return transparent.map(|digit| digit as u32);
+        }
+
+        let distance = |other: Rgb| -> u32 {
+            return (0..3).map(|i| (rgb[i] as i32 - other[i] as i32).pow(2) as u32).sum();
+        };
+        return self.colours.iter()
+            .enumerate()
+            .filter_map(|(digit, colour)| match colour {
+                Colour::Rgb(other) => Some((distance(*other), digit as u32)),
+                _ => None,
+            })
+            .min()
+            .map(|(_distance, digit)| digit);
+    }
+
+    // the digit a digit comes back as from a picture
+    #[allow(dead_code)]
+    pub fn canonical(&self, digit: u32) -> Option<u32> {
+        return match self.colour(digit) {
+            Colour::Rgb(rgb) => self.digit(rgb, 255),
+            Colour::Transparent => self.digit(UNSET_RGB, 0),
+            Colour::Unset => None,
+        };
+    }
+
+    // bright pixels are lit, e.g. to read letters
+    pub fn is_bright(&self, digit: u32) -> bool {
+        return matches!(self.colour(digit), Colour::Rgb(rgb) if image::luma(rgb) >= 128);
+    }
+
+    pub fn is_dark(&self, digit: u32) -> bool {
+        return matches!(self.colour(digit), Colour::Rgb(rgb) if image::luma(rgb) < 128);
+    }
+
+    // for printing: dark is a space, bright a star, anything else _
+    pub fn glyph(&self, digit: u32) -> char {
+        return match self.colour(digit) {
+            Colour::Rgb(rgb) if image::luma(rgb) < 128 => ' ',
+            Colour::Rgb(_) => '*',
+            _ => '_',
+        };
+    }
+
+    // for printing in colour, two spaces with the colour as background
+    pub fn ansi(&self, digit: u32) -> String {
+        return match self.colour(digit) {
+            Colour::Rgb([r, g, b]) => format!("\x1b[48;2;{r};{g};{b}m  "),
+            Colour::Transparent => "\x1b[0m  ".to_string(),
+            Colour::Unset => "\x1b[0m__".to_string(),
+        };
+    }
+
+    // pixels as a picture, each one scale x scale. Transparent pixels are
+    // see through
+    pub fn image(&self, pixels: &[u32], width: usize, scale: usize) -> Image {
+        let height = pixels.len() / width.max(1);
+        let mut image = Image::new(width * scale, height * scale, BLACK_RGB);
+
+        for (i, &pixel) in pixels.iter().enumerate() {
+            let (x, y) = (i % width, i / width);
+            let (rgb, opacity) = match self.colour(pixel) {
+                Colour::Rgb(rgb) => (rgb, 255),
+                Colour::Transparent => (UNSET_RGB, 0),
+                Colour::Unset => (UNSET_RGB, 255),
+            };
+            image.fill(x, y, scale, rgb);
+
+            if opacity != 255 {
+                for dy in 0..scale {
+                    for dx in 0..scale {
+                        image.set_opacity(x * scale + dx, y * scale + dy, opacity);
+                    }
+                }
+            }
+        }
+
+        return image;
+    }
+
+    // pixels of a picture drawn scale x scale, reading the top left of each
+    // block
+    pub fn pixels(&self, image: &Image, scale: usize) -> Result<Vec<u32>, String> {
+        let (width, height) = (image.width / scale, image.height / scale);
+
+        let mut pixels = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let (px, py) = (x * scale, y * scale);
+                let rgb = image.get(px, py).expect("inside the image");
+                pixels.push(self.digit(rgb, image.opacity(px, py)).ok_or("palette has no colours")?);
+            }
+        }
+
+        return Ok(pixels);
+    }
+}
+
+impl Sif {
+    pub fn new(data: Vec<u32>, width: usize, height: usize) -> Result<Sif, SifError> {
+        if width == 0 || height == 0 {
+            return Err(SifError::BadSize(width, height));
+        }
+        if let Some(i) = data.iter().position(|&digit| digit > 9) {
+            return Err(SifError::BadValue(i, data[i]));
+        }
+        if data.is_empty() {
+            return Err(SifError::Empty);
+        }
+        if !data.len().is_multiple_of(width * height) {
+            return Err(SifError::BadLength(data.len(), width * height));
+        }
+
+        return Ok(Sif { width, height, palette: Palette::default(), data });
+    }
+
+    // digits as written in the puzzle input, line breaks are ignored
+    pub fn parse(text: &str, width: usize, height: usize) -> Result<Sif, SifError> {
+        let data = text.chars()
+            .filter(|ch| !ch.is_whitespace())
+            .enumerate()
+            .map(|(i, ch)| ch.to_digit(10).ok_or(SifError::BadDigit(i, ch)))
+            .collect::<Result<Vec<u32>, SifError>>()?;
+
+        return Sif::new(data, width, height);
+    }
+
+    pub fn with_palette(mut self, palette: Palette) -> Self {
+        self.palette = palette;
+        return self;
+    }
+
+    // put layers together, the front one first
+    pub fn from_layers(layers: &[Vec<u32>], width: usize, height: usize) -> Result<Sif, SifError> {
+        if let Some(i) = layers.iter().position(|layer| layer.len() != width * height) {
+            let w = width.max(1);
+            return Err(SifError::LayerSize(i, w, layers[i].len() / w));
+        }
+
+        return Sif::new(layers.concat(), width, height);
+    }
+
+    // layers from pictures (PNG, PPM or PBM), the front one first, each
+    // pixel the digit of the closest colour in the palette. The first
+    // picture sets the size
+    pub fn import(filenames: &[&str], scale: usize, palette: &Palette) -> Result<Sif, String> {
+        let mut layers = Vec::new();
+        let mut size = None;
+
+        for (i, filename) in filenames.iter().enumerate() {
+            let picture = Image::load(filename)?;
+            let (width, height) = (picture.width / scale, picture.height / scale);
+            if *size.get_or_insert((width, height)) != (width, height) {
+                return Err(SifError::LayerSize(i, width, height).to_string());
+            }
+            layers.push(palette.pixels(&picture, scale)?);
+        }
+
+        let (width, height) = size.unwrap_or((0, 0));
+        let image = Sif::from_layers(&layers, width, height).map_err(|error| error.to_string())?;
+        return Ok(image.with_palette(palette.clone()));
+    }
+
+    // the digits as the puzzle gives them, one line
+    pub fn encode(&self) -> String {
+        let mut text: String = self.data.iter()
+            .map(|&digit| char::from_digit(digit, 10).expect("checked when made"))
+            .collect();
+        text.push('\n');
+        return text;
+    }
+
+    pub fn layer_size(&self) -> usize {
+        return self.width * self.height;
+    }
+
+    pub fn layers(&self) -> impl Iterator<Item = &[u32]> {
+        return self.data.chunks(self.layer_size());
+    }
+
+    #[allow(dead_code)]
+    pub fn layer(&self, i: usize) -> Option<&[u32]> {
+        return self.layers().nth(i);
+    }
+
+    // digit_counts for each layer
+    pub fn digit_counts(&self) -> Vec<Vec<usize>> {
+        return self.layers()
+            .map(|layer| digit_counts(layer).expect("Sif::new only takes digits"))
+            .collect();
+    }
+
+    // what is seen looking through every layer from the front, pixels that
+    // are transparent all the way through stay transparent
+    pub fn composite(&self) -> Vec<u32> {
+        let mut layers = self.layers();
+        let front = layers.next().expect("there is always a layer").to_vec();
+        return layers.fold(front, |picture, layer| apply_layer(&picture, layer, &self.palette));
+    }
+
+    // the pixels as a picture in the palette's colours
+    pub fn image(&self, pixels: &[u32], scale: usize) -> Image {
+        return self.palette.image(pixels, self.width, scale);
+    }
+
+    #[allow(dead_code)]
+    pub fn layer_count(&self) -> usize {
+        return self.data.len() / self.layer_size();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_digits() {
+        let counts = digit_counts(&[0, 1, 2, 2, 9]).unwrap();
+        assert_eq!(counts, vec![1, 1, 2, 0, 0, 0, 0, 0, 0, 1]);
+        assert_eq!(digit_counts(&[1, 10, 2]), Err(SifError::BadValue(1, 10)));
+    }
+
+    #[test]
+    fn from_layers_checks_digits() {
+        assert_eq!(Sif::from_layers(&[vec![0, 1], vec![2, 12]], 2, 1), Err(SifError::BadValue(3, 12)));
+        let image = Sif::from_layers(&[vec![0, 1], vec![2, 2]], 2, 1).unwrap();
+        assert_eq!(image.digit_counts(), vec![digit_counts(&[0, 1]).unwrap(), digit_counts(&[2, 2]).unwrap()]);
+    }
+}
@@ -0,0 +1,155 @@
+use std::time::Instant;
+use std::collections::BTreeMap;
+use std::f64::consts::{FRAC_PI_2, PI};
+use crate::{Point, PolarPointView, Survey};
+
+// Made up maps to time the station search on
+//
+// `--bench [WIDTHxHEIGHT[:PERCENT[:SEED]]...]` times the survey on one thread and on
+// every core, and the old ways of building a view from every asteroid (with
+// float angles as before Direction, and with exact directions) on maps small
+// enough for them, checking they all agree.
+
+const SEED: u64 = 10;
+
+// sizes timed when none are given
+const SIZES: [&str; 5] = ["40x40:30", "100x100:30", "200x200:30", "300x300:30", "400x400:30"];
+
+// the old ways take too long past this many asteroids
+const VIEWS_LIMIT: usize = 10_000;
+
+// xorshift, good enough to scatter asteroids
+fn next(state: &mut u64) -> u64 {
+    *state ^= *state << 13;
+    *state ^= *state >> 7;
+    *state ^= *state << 17;
+    return *state;
+}
+
+// a map with about percent of its places asteroids, the same for a seed
+pub fn generate(width: usize, height: usize, percent: u64, seed: u64) -> String {
+    let mut state = seed.max(1);
+    let mut text = String::with_capacity((width + 1) * height);
+
+    for _y in 0..height {
+        for _x in 0..width {
+            text.push(if next(&mut state) % 100 < percent { '#' } else { '.' });
+        }
+        text.push('\n');
+    }
+    return text;
+}
+
+// a map from WIDTHxHEIGHT:PERCENT[:SEED]
+pub fn generate_spec(spec: &str) -> Result<String, String> {
+    let bad = || format!("Bad map {spec}, expected WIDTHxHEIGHT:PERCENT[:SEED]");
+    let mut parts = spec.split(':');
+
+    let (width, height) = parts.next().and_then(|size| size.split_once('x')).ok_or_else(bad)?;
+    let width: usize = width.parse().ok().filter(|&w| w > 0).ok_or_else(bad)?;
+    let height: usize = height.parse().ok().filter(|&h| h > 0).ok_or_else(bad)?;
+    let percent: u64 = parts.next().and_then(|p| p.parse().ok()).filter(|&p| p <= 100).ok_or_else(bad)?;
+    let seed: u64 = match parts.next() {
+        Some(seed) => seed.parse().map_err(|_| bad())?,
+        None => SEED,
+    };
+    if parts.next().is_some() {
+        return Err(bad());
+    }
+
+    return Ok(generate(width, height, percent, seed));
+}
+
+// visible counts the way they were first found: a view from every asteroid
+// keyed by the bits of an f64 angle clockwise from up, each direction sorted
+// by f64 distance. Directions too close for an f64 share an angle
+fn float_visible(points: &[Point]) -> Vec<usize> {
+    return points.iter()
+        .map(|origin| {
+            let mut radials: BTreeMap<u64, Vec<(f64, Point)>> = BTreeMap::new();
+            for p in points {
+                let x = p.x as f64 - origin.x as f64;
+                let y = p.y as f64 - origin.y as f64;
+                let r = ((x * x) + (y * y)).sqrt();
+                let theta = (FRAC_PI_2 - -y.atan2(x)).rem_euclid(2.0 * PI);
+
+                if r != 0.0 {
+                    let bucket = radials.entry(theta.to_bits()).or_default();
+                    let pos = bucket.partition_point(|&(closer, _point)| closer < r);
+                    bucket.insert(pos, (r, *p));
+                }
+            }
+            radials.len()
+        })
+        .collect();
+}
+
+fn nanos(start: Instant) -> u128 {
+    return Instant::now().duration_since(start).as_nanos();
+}
+
+pub fn run(args: &[String]) -> Result<(), String> {
+    let sizes: Vec<&str> = if args.is_empty() {
+        SIZES.to_vec()
+    } else {
+        args.iter().map(|arg| arg.as_str()).collect()
+    };
+    let threads = crate::default_threads();
+
+    println!("{:>16} {:>9} {:>15} {:>15} {:>15} {:>15} {:>15}",
+        "map", "asteroids", "float views", "exact views", "1 thread", format!("{threads} cores"), "part 2");
+
+    for size in sizes {
+        let spec = if size.contains(':') { size.to_string() } else { format!("{size}:30") };
+        let map = generate_spec(&spec)
+            .map_err(|_| format!("Bad map {size}, expected WIDTHxHEIGHT[:PERCENT[:SEED]]"))?;
+        let points = crate::parse_map(&map);
+        if points.is_empty() {
+            return Err(format!("No asteroids on {spec}"));
+        }
+
+        let start = Instant::now();
+        let single = Survey::new(&points, 1);
+        let single_ns = nanos(start);
+
+        let start = Instant::now();
+        let survey = Survey::new(&points, threads);
+        let threads_ns = nanos(start);
+
+        let start = Instant::now();
+        let zapped = survey.view(survey.station).vaporize().count();
+        let part2_ns = nanos(start);
+
+        if single.visible != survey.visible || zapped != points.len() - 1 {
+            return Err(format!("{spec}: the surveys don't agree"));
+        }
+
+        // the old ways, every asteroid's view of every other. Float angles
+        // can be wrong, that is why they went
+        let (mut float_views, mut exact_views) = ("-".to_string(), "-".to_string());
+        let mut miscounted = 0;
+        if points.len() <= VIEWS_LIMIT {
+            let start = Instant::now();
+            let visible = float_visible(&points);
+            float_views = nanos(start).to_string();
+            miscounted = visible.iter().zip(&survey.visible).filter(|(float, exact)| float != exact).count();
+
+            let start = Instant::now();
+            let visible: Vec<usize> = points.iter()
+                .map(|p| PolarPointView::build(p, &points).visible())
+                .collect();
+            exact_views = nanos(start).to_string();
+            if visible != survey.visible {
+                return Err(format!("{spec}: the views don't agree with the survey"));
+            }
+        }
+
+        println!("{spec:>16} {:>9} {float_views:>15} {exact_views:>15} {single_ns:>15} {threads_ns:>15} {part2_ns:>15}",
+            points.len());
+        if miscounted > 0 {
+            println!("{:>16} float angles miscount what {miscounted} asteroids see", "");
+        }
+    }
+
+    return Ok(());
+}
@@ -1,96 +1,217 @@
 use std::env;
 use std::fs;
+use std::io;
 use std::process;
+use std::thread;
 use std::time::Instant; // 0.8.2
 use std::cmp::Ordering;
 use std::collections::BTreeMap;
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+mod bench;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 struct Point {
     x: usize,
     y: usize,
     c: char,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+// Direction from one point to another as the smallest whole number step
+// (dx / g, dy / g), so points on the same line of sight have exactly the same
+// direction. Ordered clockwise from straight up (y grows downwards).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Direction {
+    dx: i64,
+    dy: i64,
+}
+
+fn gcd(a: i64, b: i64) -> i64 {
+    let (mut a, mut b) = (a.abs(), b.abs());
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    return a;
+}
+
+impl Direction {
+    // None for no step at all
+    fn new(dx: i64, dy: i64) -> Option<Self> {
+        let g = gcd(dx, dy);
+        if g == 0 {
+            return None;
+        }
+        return Some(Direction { dx: dx / g, dy: dy / g });
+    }
+
+    // 0 for up to just before straight down (the right half), 1 for the rest
+    fn half(&self) -> u8 {
+        return if self.dx > 0 || (self.dx == 0 && self.dy < 0) { 0 } else { 1 };
+    }
+}
+
+impl Ord for Direction {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // within a half, a comes first if b is clockwise of it: with y down
+        // that is a positive cross product
+        let cross = self.dx * other.dy - self.dy * other.dx;
+        return self.half().cmp(&other.half()).then(0.cmp(&cross));
+    }
+}
+
+impl PartialOrd for Direction {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// Another point and how far away it is, squared so it stays exact
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 struct Neighbor {
-    r: f64,
+    r2: i64,
     point: Point,
 }
 
-impl Eq for Neighbor {}
 impl Ord for Neighbor {
     fn cmp(&self, other: &Self) -> Ordering {
-        self.r.partial_cmp(&other.r)
-            .unwrap_or_else(|| {    // put NaN at end
-                if self.r.is_nan() && other.r.is_nan() {
-                    Ordering::Equal
-                } else if self.r.is_nan() {
-                    Ordering::Greater
-                } else {
-                    Ordering::Less
-                }
-            })
+        self.r2.cmp(&other.r2)
     }
 }
+
 impl PartialOrd for Neighbor {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         Some(self.cmp(other))
     }
 }
 
-// A point (x, y) with a map of all other points converted to polar coordinates
-// the other points are stored in a BTreeMap keyed by their theta (with 0 being
-// the positive Y axis, and clockwise increasing values). The neighbors are then
-// in a vector kept sorted by their distance from the origin, closest first.
+// A point (x, y) with a map of all other points by direction from it. The
+// directions are kept in clockwise order from the negative Y axis (up). The
+// neighbors in each direction are in a vector kept sorted by their distance
+// from the origin, closest first.
 struct PolarPointView {
     x: usize,
     y: usize,
-    radials: BTreeMap<u64, Vec<Neighbor>>
+    radials: BTreeMap<Direction, Vec<Neighbor>>
 }
 
 impl PolarPointView {
     // build a new PolarPointView from origin of all points
-    fn build(origin: &Point, points: &Vec<&Point>) -> Self {
-        let mut me = Self { 
-            x: origin.x, 
-            y: origin.y, 
+    fn build(origin: &Point, points: &[Point]) -> Self {
+        let mut me = Self {
+            x: origin.x,
+            y: origin.y,
             radials: BTreeMap::new()
         };
 
         points.iter().for_each(|p| me.insert(p));
+        me.radials.values_mut().for_each(|bucket| bucket.sort_unstable());
         return me;
     }
 
-    // insert point as polar coordinates relative to current origin point
-    // stored as BTreeMap keyed on theta in vectors sorted by their distance
-    // (r) from the origin.
+    // add point by its direction and distance from the origin point, the
+    // buckets are sorted by distance once everything is in
     fn insert(&mut self, p: &Point) {
         // normalize p to self
-        let x = p.x as f64 - self.x as f64;
-        let y = p.y as f64 - self.y as f64;
-
-        // standard angle from +X, CCW
-        // rotate so 0 at +Y, flip to CW
-        let r = ((x * x) + (y * y)).sqrt();
-        let theta = (std::f64::consts::FRAC_PI_2 - -y.atan2(x)).rem_euclid(2.0 * std::f64::consts::PI);
+        let x = p.x as i64 - self.x as i64;
+        let y = p.y as i64 - self.y as i64;
 
         // don't insert self
-        if r != 0.0 {
-            // cant use f64 as BTreeMap key, use bit-pattern as usize
-            let bucket = self.radials.entry(f64::to_bits(theta)).or_insert_with(Vec::new);
-            let neighbor = Neighbor { r, point: p.clone() };
-            match bucket.binary_search(&neighbor) {
-                Ok(pos) | Err(pos) => bucket.insert(pos, neighbor)
+        if let Some(direction) = Direction::new(x, y) {
+            let neighbor = Neighbor { r2: x * x + y * y, point: *p };
+            self.radials.entry(direction).or_insert_with(Vec::new).push(neighbor);
+        }
+    }
+}
+
+// One asteroid hit by the laser, and on which turn of the laser (from 1)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Zap {
+    rotation: usize,
+    point: Point,
+}
+
+// Every asteroid in the order the laser vaporizes them: each turn clockwise
+// from up takes the closest asteroid left in every direction
+struct Vaporize<'a> {
+    rotation: usize,
+    turn: Vec<&'a [Neighbor]>,      // directions still to fire at this turn
+    next: Vec<&'a [Neighbor]>,      // what is left for the next turn
+}
+
+impl<'a> Iterator for Vaporize<'a> {
+    type Item = Zap;
+
+    fn next(&mut self) -> Option<Zap> {
+        if self.turn.is_empty() {
+            if self.next.is_empty() {
+                return None;
             }
+            // the turn is kept reversed so the next direction pops off the end
+            self.turn = self.next.drain(..).rev().collect();
+            self.rotation += 1;
         }
+
+        let line = self.turn.pop()?;
+        if line.len() > 1 {
+            self.next.push(&line[1..]);
+        }
+        return Some(Zap { rotation: self.rotation, point: line[0].point });
+    }
+}
+
+// What is seen looking from one asteroid towards a place
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Sight {
+    Clear,              // the asteroid there is in view
+    Blocked(Point),     // the closest asteroid in the way
+    Empty,              // there is no (other) asteroid there
+}
+
+impl PolarPointView {
+    // asteroids in view, one for each direction
+    fn visible(&self) -> usize {
+        return self.radials.len();
+    }
+
+    fn sight(&self, x: usize, y: usize) -> Sight {
+        let (dx, dy) = (x as i64 - self.x as i64, y as i64 - self.y as i64);
+        let Some(line) = Direction::new(dx, dy).and_then(|direction| self.radials.get(&direction)) else {
+            return Sight::Empty;
+        };
+
+        if !line.iter().any(|neighbor| (neighbor.point.x, neighbor.point.y) == (x, y)) {
+            return Sight::Empty;
+        }
+        return match line[0].point {
+            first if (first.x, first.y) == (x, y) => Sight::Clear,
+            first => Sight::Blocked(first),
+        };
+    }
+
+    // every other point in the order the laser vaporizes them
+    fn vaporize(&self) -> Vaporize<'_> {
+        return Vaporize {
+            rotation: 1,
+            turn: self.radials.values().rev().map(|line| line.as_slice()).collect(),
+            next: Vec::new(),
+        };
     }
 }
 
 fn read_data(filename: &str) -> Vec<Point> {
+    // random:WIDTHxHEIGHT:PERCENT[:SEED] makes up a map instead
+    let text = match filename.strip_prefix("random:") {
+        Some(spec) => bench::generate_spec(spec).unwrap_or_else(|error| {
+            println!("ERROR: {error}");
+            process::exit(1);
+        }),
+        None => fs::read_to_string(filename).expect("Expected input file to exist"),
+    };
+    return parse_map(&text);
+}
+
+fn parse_map(text: &str) -> Vec<Point> {
     // create vector of points and their value
-    let points = fs::read_to_string(filename)
-        .expect("Expected input file to exist")
+    let points = text
         .split('\n')
         .enumerate()
         .map(|(y, line)| line.chars()
@@ -105,37 +226,242 @@ fn read_data(filename: &str) -> Vec<Point> {
     return points;
 }
 
-fn part1(points: &Vec<Point>) -> usize {
-    let asteroids: Vec<&Point> = points.iter().collect();
+// The reduced direction of every step that fits on a map, so counting what
+// a station sees is a lookup per asteroid rather than a gcd. Steps are laid
+// out in a (2 width - 1) x (2 height - 1) grid with no step in the middle,
+// and each holds the cell of its reduced step.
+//
+// A point's offset is where it is in a grid that wide, so the step from a
+// to b is cell centre + offset(b) - offset(a): with the points in reading
+// order the lookups for a station go through the grid from start to end.
+struct Steps {
+    stride: usize,      // 2 width - 1
+    centre: usize,      // cell of no step
+    reduced: Vec<u32>,
+}
+
+impl Steps {
+    fn new(width: usize, height: usize) -> Self {
+        let (w, h) = (width as i64, height as i64);
+        let stride = 2 * width - 1;
+        let mut reduced = Vec::with_capacity(stride * (2 * height - 1));
+
+        for dy in 1 - h..h {
+            for dx in 1 - w..w {
+                // no step at all is its own direction, nothing else is
+                let g = gcd(dx, dy).max(1);
+                let (rx, ry) = (dx / g + w - 1, dy / g + h - 1);
+                reduced.push((ry * stride as i64 + rx) as u32);
+            }
+        }
+        return Steps { stride, centre: (height - 1) * stride + width - 1, reduced };
+    }
+
+    fn offset(&self, p: &Point) -> usize {
+        return p.y * self.stride + p.x;
+    }
+
+    // a bit for every cell, small enough to stay in cache
+    fn bits(&self) -> Vec<u64> {
+        return vec![0; self.reduced.len().div_ceil(64)];
+    }
+
+    // asteroids seen from the asteroid at station (an offset), one per
+    // direction. seen is scratch space from bits() where a direction's bit
+    // is set once seen
+    fn visible(&self, station: usize, offsets: &[usize], seen: &mut [u64]) -> usize {
+        seen.fill(0);
+
+        // every offset is at most the centre's, so this never goes below 0
+        let steps = &self.reduced[self.centre - station..];
+        let mut count = 0;
+        for &offset in offsets {
+            let direction = steps[offset] as usize;
+            let (word, bit) = (direction / 64, 1 << (direction % 64));
+            count += (seen[word] & bit == 0) as usize;
+            seen[word] |= bit;
+        }
+        // the station itself was counted as the no step direction
+        return count - 1;
+    }
+}
+
+// How many asteroids each asteroid sees, worked out once for both parts,
+// and the best one for the station
+struct Survey<'a> {
+    points: &'a [Point],
+    visible: Vec<usize>,    // for each point
+    station: usize,         // index of the point that sees most
+}
+
+// threads to use for the survey, one per core
+fn default_threads() -> usize {
+    return thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+}
+
+impl<'a> Survey<'a> {
+    // count for every asteroid, the asteroids split between threads
+    fn new(points: &'a [Point], threads: usize) -> Self {
+        let width = points.iter().map(|p| p.x + 1).max().unwrap_or(1);
+        let height = points.iter().map(|p| p.y + 1).max().unwrap_or(1);
+        let steps = Steps::new(width, height);
+        let offsets: Vec<usize> = points.iter().map(|p| steps.offset(p)).collect();
+
+        let mut visible = vec![0; points.len()];
+        let chunk = points.len().div_ceil(threads.max(1)).max(1);
+        thread::scope(|scope| {
+            for (i, counts) in visible.chunks_mut(chunk).enumerate() {
+                let (steps, offsets) = (&steps, &offsets);
+                scope.spawn(move || {
+                    let mut seen = steps.bits();
+                    for (j, count) in counts.iter_mut().enumerate() {
+                        let station = i * chunk + j;
+                        *count = steps.visible(offsets[station], offsets, &mut seen);
+                    }
+                });
+            }
+        });
+
+        // the last of equals, as the views used to
+        let station = (0..points.len())
+            .max_by_key(|&i| visible[i])
+            .expect("Expected at least one asteroid");
+        return Survey { points, visible, station };
+    }
+
+    // every other asteroid as seen from one of them
+    fn view(&self, i: usize) -> PolarPointView {
+        return PolarPointView::build(&self.points[i], self.points);
+    }
+
+    fn find(&self, x: usize, y: usize) -> Option<usize> {
+        return self.points.iter().position(|p| (p.x, p.y) == (x, y));
+    }
+}
+
+// the map with how many asteroids each asteroid sees in its place
+fn count_grid(survey: &Survey) -> String {
+    let width = survey.points.iter().map(|p| p.x + 1).max().unwrap_or(0);
+    let height = survey.points.iter().map(|p| p.y + 1).max().unwrap_or(0);
+    let cell = survey.visible.iter().map(|count| count.to_string().len()).max().unwrap_or(1);
+
+    let mut grid = vec![vec![format!("{:>cell$}", "."); width]; height];
+    for (p, count) in survey.points.iter().zip(&survey.visible) {
+        grid[p.y][p.x] = format!("{count:>cell$}");
+    }
+
+    return grid.iter().map(|row| row.join(" ") + "\n").collect();
+}
+
+fn save_counts(survey: &Survey, filename: &str) -> io::Result<()> {
+    let mut text = String::from("x,y,visible\n");
+    for (p, count) in survey.points.iter().zip(&survey.visible) {
+        text.push_str(&format!("{},{},{count}\n", p.x, p.y));
+    }
+    return fs::write(filename, text);
+}
+
+fn show_sight(survey: &Survey, (x1, y1): (usize, usize), (x2, y2): (usize, usize)) {
+    let Some(i) = survey.find(x1, y1) else {
+        println!("ERROR: no asteroid at {x1},{y1}");
+        return;
+    };
 
-    let base = asteroids.iter()
-        .map(|asteroid| PolarPointView::build(*asteroid, &asteroids))
-        .max_by_key(|asteroid| asteroid.radials.len())
-        .unwrap();
+    match survey.view(i).sight(x2, y2) {
+        Sight::Clear => println!("{x1},{y1} can see {x2},{y2}"),
+        Sight::Blocked(p) => println!("{x1},{y1} can't see {x2},{y2}, {},{} is in the way", p.x, p.y),
+        Sight::Empty => println!("ERROR: no other asteroid at {x2},{y2}"),
+    }
+}
+
+fn part1(survey: &Survey, options: &Options) -> usize {
+    let base = &survey.points[survey.station];
+    println!("station at {},{}", base.x, base.y);
+
+    if options.grid {
+        print!("{}", count_grid(survey));
+    }
+    if let Some(filename) = &options.counts && let Err(error) = save_counts(survey, filename) {
+        println!("ERROR: {filename}: {error}");
+    }
+    for &(from, to) in &options.sights {
+        show_sight(survey, from, to);
+    }
 
-    return base.radials.len();
+    return survey.visible[survey.station];
 }
 
-fn part2(points: &Vec<Point>) -> usize {
-    let asteroids: Vec<&Point> = points.iter().collect();
+fn part2(survey: &Survey, options: &Options) -> Result<usize, String> {
+    // the station found in part 1 and everything around it
+    let base = survey.view(survey.station);
 
-    // get best location for base and the asteroids that are visible from there
-    let base = asteroids.iter()
-        .map(|asteroid| PolarPointView::build(*asteroid, &asteroids))
-        .max_by_key(|asteroid| asteroid.radials.len())
-        .unwrap();
+    if let Some(filename) = &options.order && let Err(error) = save_order(&base, filename) {
+        println!("ERROR: {filename}: {error}");
+    }
 
-    // get list of theta values that are visible
-    let thetas: Vec<_> = base.radials.keys().collect();
+    // the nth asteroid zapped, small (test) maps may not have that many
+    let Some(lasteroid) = base.vaporize().nth(options.nth - 1) else {
+        let count = base.vaporize().count();
+        return Err(format!("only {count} asteroids to vaporize, no asteroid {}", options.nth));
+    };
 
-    // theta value of last asteroid to be zapped (based on test vs live input)
-    let last_index = if thetas.len() < 200 { thetas.len() } else { 199 };
+    return Ok(lasteroid.point.x * 100 + lasteroid.point.y);
+}
 
-    // unwrap the original point for the last asteroid zapped
-    let lasteroid = base.radials[thetas[last_index]].first().unwrap().point;
+// the whole vaporization order as CSV
+fn save_order(base: &PolarPointView, filename: &str) -> io::Result<()> {
+    let mut text = String::from("n,x,y,rotation\n");
+    for (i, zap) in base.vaporize().enumerate() {
+        text.push_str(&format!("{},{},{},{}\n", i + 1, zap.point.x, zap.point.y, zap.rotation));
+    }
+    return fs::write(filename, text);
+}
 
-    // println!("Lasteroid = {lasteroid:?}");
-    return lasteroid.x * 100 + lasteroid.y;
+struct Options {
+    nth: usize,                 // --nth N, asteroid to find in part 2
+    order: Option<String>,      // --order FILE, save the vaporization order as CSV
+    grid: bool,                 // --grid, show how many each asteroid sees
+    counts: Option<String>,     // --counts FILE, save how many each asteroid sees as CSV
+    sights: Vec<((usize, usize), (usize, usize))>,  // --sight X,Y X,Y, can one see the other
+    threads: usize,             // --threads N, for the part 1 search
+}
+
+fn read_position(arg: Option<&String>) -> Result<(usize, usize), String> {
+    let text = arg.ok_or("--sight needs two positions X,Y X,Y")?;
+    let bad = || format!("Bad position {text}, expected X,Y");
+    let (x, y) = text.split_once(',').ok_or_else(bad)?;
+    return Ok((x.trim().parse().map_err(|_| bad())?, y.trim().parse().map_err(|_| bad())?));
+}
+
+fn read_options(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        nth: 200, order: None, grid: false, counts: None, sights: Vec::new(), threads: default_threads(),
+    };
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--nth" => {
+                let n = args.next().ok_or("--nth needs a number")?;
+                options.nth = n.parse().ok().filter(|&n| n > 0).ok_or(format!("Bad asteroid number {n}"))?;
+            },
+            "--order" => options.order = Some(args.next().ok_or("--order needs a file")?.clone()),
+            "--grid" => options.grid = true,
+            "--counts" => options.counts = Some(args.next().ok_or("--counts needs a file")?.clone()),
+            "--sight" => {
+                let from = read_position(args.next())?;
+                let to = read_position(args.next())?;
+                options.sights.push((from, to));
+            },
+            "--threads" => {
+                let n = args.next().ok_or("--threads needs a number")?;
+                options.threads = n.parse().ok().filter(|&n| n > 0).ok_or(format!("Bad thread count {n}"))?;
+            },
+            _ => return Err(format!("Unknown option {arg}")),
+        }
+    }
+
+    return Ok(options);
 }
 
 fn main() {
@@ -145,6 +471,20 @@ fn main() {
         process::exit(1);
     }
 
+    // --bench [SIZE...] times the search on generated maps instead
+    if args[1] == "--bench" {
+        if let Err(error) = bench::run(&args[2..]) {
+            println!("ERROR: {error}");
+            process::exit(1);
+        }
+        return;
+    }
+
+    let options = read_options(&args[2..]).unwrap_or_else(|error| {
+        println!("ERROR: {error}");
+        process::exit(1);
+    });
+
     let start = Instant::now();
 
     let data = read_data(&args[1]);
@@ -154,14 +494,18 @@ fn main() {
     );
 
     let p1_start = Instant::now();
-    let p1 = part1(&data);
+    let survey = Survey::new(&data, options.threads);
+    let p1 = part1(&survey, &options);
     println!(
         "{p1:>15} ({:9.9} ns)",
         Instant::now().duration_since(p1_start).as_nanos()
     );
 
     let p2_start = Instant::now();
-    let p2 = part2(&data);
+    let p2 = part2(&survey, &options).unwrap_or_else(|error| {
+        println!("ERROR: {error}");
+        process::exit(1);
+    });
 
     println!(
         "{p2:>15} ({:9.9} ns)",
@@ -172,3 +516,240 @@ fn main() {
         Instant::now().duration_since(start).as_nanos()
     );
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    // the large example from the puzzle, the station is at 11,13
+    const LARGE: &str = "\
+        .#..##.###...#######\n\
+        ##.############..##.\n\
+        .#.######.########.#\n\
+        .###.#######.####.#.\n\
+        #####.##.#.##.###.##\n\
+        ..#####..#.#########\n\
+        ####################\n\
+        #.####....###.#.#.##\n\
+        ##.#################\n\
+        #####.##.###..####..\n\
+        ..######..##.#######\n\
+        ####.##.####...##..#\n\
+        .#####..#.######.###\n\
+        ##...#.##########...\n\
+        #.##########.#######\n\
+        .####.#.###.###.#.##\n\
+        ....##.##.###..#####\n\
+        .#.#.###########.###\n\
+        #.#.#.#####.####.###\n\
+        ###.##.####.##.#..##\n";
+
+    // the other examples: the map, where the station goes and what it sees
+    const EXAMPLES: [(&str, (usize, usize), usize); 4] = [
+        (".#..#\n.....\n#####\n....#\n...##\n", (3, 4), 8),
+        ("......#.#.\n#..#.#....\n..#######.\n.#.#.###..\n.#..#.....\n\
+          ..#....#.#\n#..#....#.\n.##.#..###\n##...#..#.\n.#....####\n", (5, 8), 33),
+        ("#.#...#.#.\n.###....#.\n.#....#...\n##.#.#.#.#\n....#.#.#.\n\
+          .##..###.#\n..#...##..\n..##....##\n......#...\n.####.###.\n", (1, 2), 35),
+        (".#..#..###\n####.###.#\n....###.#.\n..###.##.#\n##.##.#.#.\n\
+          ....###..#\n..#.#..#.#\n#..#.#.###\n.##...##.#\n.....#.#..\n", (6, 3), 41),
+    ];
+
+    fn options(nth: usize) -> Options {
+        return Options { nth, order: None, grid: false, counts: None, sights: Vec::new(), threads: 1 };
+    }
+
+    fn point(x: usize, y: usize) -> Point {
+        return Point { x, y, c: '#' };
+    }
+
+    // what an asteroid sees, by counting distinct reduced directions
+    fn brute_visible(points: &[Point], from: &Point) -> usize {
+        let directions: HashSet<Direction> = points.iter()
+            .filter_map(|p| Direction::new(p.x as i64 - from.x as i64, p.y as i64 - from.y as i64))
+            .collect();
+        return directions.len();
+    }
+
+    fn direction(dx: i64, dy: i64) -> Direction {
+        return Direction::new(dx, dy).expect("a step");
+    }
+
+    #[test]
+    fn steps_are_reduced() {
+        assert_eq!(direction(2, 4), direction(1, 2));
+        assert_eq!(direction(-3, 0), direction(-1, 0));
+        assert_ne!(direction(-2, -4), direction(1, 2));
+        assert_eq!(Direction::new(0, 0), None);
+    }
+
+    #[test]
+    fn nearly_the_same_direction() {
+        // a hair apart, too close for float angles
+        let (a, b) = (direction(1, 9999), direction(1, 10000));
+        assert_ne!(a, b);
+        assert!(a < b);
+        assert!(direction(-1, -9999) < direction(-1, -10000));
+    }
+
+    #[test]
+    fn opposite_directions() {
+        for (dx, dy) in [(0, -1), (1, -1), (1, 0), (3, 7), (1, 10000)] {
+            let (there, back) = (direction(dx, dy), direction(-dx, -dy));
+            assert!(there < back, "({dx}, {dy}) should come before its opposite");
+            assert_eq!(there.cmp(&back), back.cmp(&there).reverse());
+        }
+    }
+
+    #[test]
+    fn axes_split_the_turn() {
+        // clockwise from straight up, with a direction each side of every axis
+        let order = [
+            (0, -1), (1, -1000),
+            (1000, -1), (1, 0), (1000, 1),
+            (1, 1000), (0, 1), (-1, 1000),
+            (-1000, 1), (-1, 0), (-1000, -1),
+            (-1, -1000),
+        ];
+        let expected: Vec<Direction> = order.iter().map(|&(dx, dy)| direction(dx, dy)).collect();
+
+        let mut sorted = expected.clone();
+        sorted.reverse();
+        sorted.sort();
+        assert_eq!(sorted, expected);
+    }
+
+    #[test]
+    fn view_groups_lines_of_sight() {
+        // two lines through the origin, each with a near miss either side,
+        // the far asteroids first so the buckets have to be sorted
+        let points = [
+            point(1000, 998), point(1500, 1000), point(0, 0), point(500, 499),
+            point(499, 498), point(1001, 999), point(750, 500), point(3, 2),
+            point(1499, 999),
+        ];
+        let view = PolarPointView::build(&points[2], &points);
+        assert_eq!(view.visible(), 5);
+        assert_eq!(view.visible(), brute_visible(&points, &points[2]));
+
+        let line: Vec<Point> = view.radials[&direction(500, 499)].iter().map(|n| n.point).collect();
+        assert_eq!(line, [point(500, 499), point(1000, 998)]);
+        let line: Vec<Point> = view.radials[&direction(3, 2)].iter().map(|n| n.point).collect();
+        assert_eq!(line, [point(3, 2), point(750, 500), point(1500, 1000)]);
+
+        assert_eq!(view.sight(1000, 998), Sight::Blocked(point(500, 499)));
+        assert_eq!(view.sight(1500, 1000), Sight::Blocked(point(3, 2)));
+        assert_eq!(view.sight(499, 498), Sight::Clear);
+        assert_eq!(view.sight(1001, 999), Sight::Clear);
+        assert_eq!(view.sight(1499, 999), Sight::Clear);
+        assert_eq!(view.sight(2, 2), Sight::Empty);
+    }
+
+    #[test]
+    fn random_maps_agree_with_brute_force() {
+        for (size, percent, seed) in [(1, 100, 1), (7, 50, 2), (20, 30, 3), (31, 10, 4), (25, 60, 5)] {
+            let points = parse_map(&bench::generate(size, size + 3, percent, seed));
+            let expected: Vec<usize> = points.iter().map(|p| brute_visible(&points, p)).collect();
+
+            let views: Vec<usize> = points.iter().map(|p| PolarPointView::build(p, &points).visible()).collect();
+            assert_eq!(views, expected, "views of {size}x{}:{percent}:{seed}", size + 3);
+            assert_eq!(Survey::new(&points, 1).visible, expected, "survey of {size}x{}:{percent}:{seed}", size + 3);
+        }
+    }
+
+    #[test]
+    fn large_map_near_lines() {
+        // (n + 1, n) are all different directions from the corner, a hair
+        // apart far out, and each hides (2n + 2, 2n) behind it
+        let mut points = vec![point(0, 0), point(1499, 1499)];
+        for n in 0..1499 {
+            points.push(point(n + 1, n));
+            if 2 * n + 2 < 1500 {
+                points.push(point(2 * n + 2, 2 * n));
+            }
+        }
+        points.sort_by_key(|p| (p.y, p.x));
+
+        // counting every station by brute force is slow, check a sample
+        let survey = Survey::new(&points, 1);
+        for i in (0..points.len()).step_by(37) {
+            assert_eq!(survey.visible[i], brute_visible(&points, &points[i]), "from {:?}", points[i]);
+        }
+
+        let corner = survey.find(0, 0).unwrap();
+        assert_eq!(survey.visible[corner], 1500);
+        assert_eq!(survey.view(corner).visible(), 1500);
+    }
+
+    #[test]
+    fn vaporizes_the_large_example() {
+        let points = parse_map(LARGE);
+        let survey = Survey::new(&points, 1);
+        let base = survey.view(survey.station);
+        let order: Vec<Zap> = base.vaporize().collect();
+        assert_eq!(order.len(), 299);
+
+        for (n, x, y) in [(1, 11, 12), (2, 12, 1), (3, 12, 2), (10, 12, 8), (20, 16, 0), (50, 16, 9),
+            (100, 10, 16), (199, 9, 6), (200, 8, 2), (201, 10, 9), (299, 11, 1)] {
+            assert_eq!((order[n - 1].point.x, order[n - 1].point.y), (x, y), "asteroid {n}");
+        }
+        assert_eq!(order[0].rotation, 1);
+        assert_eq!(order[298].rotation, order.iter().map(|zap| zap.rotation).max().unwrap());
+
+        assert_eq!(part2(&survey, &options(200)), Ok(802));
+        assert_eq!(part2(&survey, &options(299)), Ok(1101));
+    }
+
+    #[test]
+    fn nth_past_the_last_asteroid() {
+        let points = parse_map(LARGE);
+        let survey = Survey::new(&points, 1);
+        assert_eq!(part2(&survey, &options(300)), Err("only 299 asteroids to vaporize, no asteroid 300".to_string()));
+
+        let points = parse_map(".#..#\n.....\n#####\n....#\n...##\n");
+        let survey = Survey::new(&points, 1);
+        assert_eq!(part2(&survey, &options(9)), Ok(100));
+        assert_eq!(part2(&survey, &options(200)), Err("only 9 asteroids to vaporize, no asteroid 200".to_string()));
+    }
+
+    #[test]
+    fn threads_agree_on_the_examples() {
+        let large = [(LARGE, (11, 13), 210)];
+        for (map, (x, y), count) in EXAMPLES.iter().chain(&large) {
+            let points = parse_map(map);
+            let single = Survey::new(&points, 1);
+            assert_eq!((points[single.station].x, points[single.station].y), (*x, *y));
+            assert_eq!(single.visible[single.station], *count);
+
+            // more threads than asteroids leaves some without any
+            for threads in [2, 3, 7, points.len() + 5] {
+                let survey = Survey::new(&points, threads);
+                assert_eq!(survey.visible, single.visible, "{threads} threads");
+                assert_eq!(survey.station, single.station, "{threads} threads");
+                assert_eq!(count_grid(&survey), count_grid(&single));
+                assert_eq!(part1(&survey, &options(1)), *count);
+            }
+        }
+    }
+
+    #[test]
+    fn grid_and_sight_from_threads() {
+        let points = parse_map(EXAMPLES[0].0);
+        let survey = Survey::new(&points, 4);
+        assert_eq!(count_grid(&survey), ". 7 . . 7\n. . . . .\n6 7 7 7 5\n. . . . 7\n. . . 8 7\n");
+
+        let view = survey.view(survey.find(3, 4).unwrap());
+        assert_eq!(view.sight(1, 0), Sight::Blocked(point(2, 2)));
+        assert_eq!(view.sight(4, 0), Sight::Clear);
+        assert_eq!(view.sight(0, 0), Sight::Empty);
+        assert_eq!(survey.find(0, 0), None);
+
+        let large = parse_map(LARGE);
+        let (single, survey) = (Survey::new(&large, 1), Survey::new(&large, 6));
+        let (a, b) = (single.view(single.station), survey.view(survey.station));
+        for p in &large {
+            assert_eq!(a.sight(p.x, p.y), b.sight(p.x, p.y), "{},{}", p.x, p.y);
+        }
+    }
+}
@@ -2,85 +2,48 @@ use std::env;
 use std::process;
 use std::cmp;
 use std::time::Instant; // 0.8.2
-use std::collections::HashMap;
 
 pub mod intcode;
+mod image;
+mod ocr;
+mod render;
+mod robot;
 
-#[derive(Clone, Copy, Hash, Eq, PartialEq, Debug)]
-struct Point {
-	x: i64,
-	y: i64,
-}
-
-impl Point {
-	fn add(&mut self, other: &Point) {
-		self.x += other.x;
-		self.y += other.y;
-	}
-}
+use robot::{Config, Hull, Point, Robot};
 
 fn read_data(filename: &str) -> Vec<i64> {
     return intcode::read_program(filename);
 }
 
-// Runs paint program and returns vector of painted panels
-fn paint(program: &Vec<i64>, start_color: i64) -> HashMap<Point, i64> {
-    let mut position = Point { x: 0, y: 0 };
-    let mut direction = Point { x: 0, y: -1 };
-    let mut painted: HashMap<Point, i64> = HashMap::new();
-    let mut cpu = intcode::build_cpu(program, &vec![start_color]);
-
-    while !cpu.is_terminated() {
-        cpu.run();
-
-        if let Some(color) = cpu.pop_output() {
-            painted.insert(position, color);
-
-            if let Some(dir) = cpu.pop_output() { 
-                direction = match dir {
-                    0 => { Point { x: -direction.y, y: direction.x } },
-                    1 => { Point { x: direction.y, y: -direction.x } },
-                    _ => { Point { x: 0, y: 0 } },
-                };
-
-                // println!("Paint {position:?} = {color} move {direction:?}");
-                position.add(&direction);
-
-                // get color under panel and push as input to next iteration
-                cpu.push_input(
-                    match painted.get(&position) {
-                        Some(c) => *c,  // painted color
-                        None => 0,   // black
-                    }
-                );
+// Runs paint program from a starting panel colour and returns the robot
+// with the hull it painted
+fn paint(program: &Vec<i64>, start_color: i64, stats: bool) -> Robot {
+    let mut robot = Robot::new(program, Config { colour: start_color, ..Config::default() });
 
-            } else {
-                println!("ERROR: No output direction!");
-                break;
-            }
-        } else {
-            println!("ERROR: No output color!");
-            break;
+    if let Err(error) = robot.run() {
+        println!("ERROR: {error}");
+    }
+
+    let hull = robot.hull();
+    if stats {
+        println!("painted {} panels, {} repaints in {} moves", hull.painted(), hull.repaints(), robot.moves());
+        if let Some((p, count)) = hull.most_painted() {
+            println!("most painted ({}, {}) {count} times", p.x, p.y);
         }
     }
 
-    return painted;
+    return robot;
 }
 
-fn show(panels: &HashMap<Point, i64>) {
-    let x1 = panels.iter().min_by_key(|(p, _c)| p.x).map(|(p, _c)| p.x).unwrap();
-    let x2 = panels.iter().max_by_key(|(p, _c)| p.x).map(|(p, _c)| p.x).unwrap();
-    let y1 = panels.iter().min_by_key(|(p, _c)| p.y).map(|(p, _c)| p.y).unwrap();
-    let y2 = panels.iter().max_by_key(|(p, _c)| p.y).map(|(p, _c)| p.y).unwrap();
+fn show(hull: &Hull) {
+    let Some((Point { x: x1, y: y1 }, Point { x: x2, y: y2 })) = hull.bounds() else {
+        return;
+    };
 
     for y in cmp::min(y1, y2)..=cmp::max(y1, y2) {
-        // need to reverse to see it in the camera?
-        for x in (cmp::min(x1, x2)..=cmp::max(x1, x2)).rev() {
+        for x in cmp::min(x1, x2)..=cmp::max(x1, x2) {
             let position = Point {x: x, y: y};
-            let color = match panels.get(&position) {
-                Some(c) => *c,
-                None => 0,   // black
-            };
+            let color = hull.colour(position);
 
             if color == 1 {
                 print!("#");
@@ -93,18 +56,98 @@ fn show(panels: &HashMap<Point, i64>) {
     }
 }
 
-fn part1(program: &Vec<i64>) -> usize {
-    let painted = paint(program, 0);
+// read the registration identifier painted on the hull
+fn read_hull(hull: &Hull) -> ocr::Reading {
+    let Some((Point { x: x1, y: y1 }, Point { x: x2, y: y2 })) = hull.bounds() else {
+        return Err(String::new());
+    };
 
-    return painted.len();
+    let mut pixels = Vec::new();
+    for y in y1..=y2 {
+        for x in x1..=x2 {
+            pixels.push(hull.colour(Point { x, y }) == robot::WHITE);
+        }
+    }
+
+    return ocr::read(&pixels, (x2 - x1 + 1) as usize);
 }
 
-fn part2(program: &Vec<i64>) -> usize {
-    let painted = paint(program, 1);
+// save the pictures asked for
+fn export(robot: &Robot, options: &Options) {
+    let visits = robot.visits();
+    let area = render::area(robot.hull(), &visits);
 
-    show(&painted);
+    if let Some(filename) = &options.image {
+        let heat = if options.heat { Some(&visits) } else { None };
+        let image = render::hull(robot.hull(), area, options.scale, heat);
+        if let Err(error) = image.save(filename) {
+            println!("ERROR: {filename}: {error}");
+        }
+    }
 
-    return painted.len();
+    if let Some(filename) = &options.animate && let Err(error) = render::animate(robot.config(), robot.path(), area, options.scale, options.every, filename) {
+        println!("ERROR: {filename}: {error}");
+    }
+}
+
+fn part1(program: &Vec<i64>, options: &Options) -> usize {
+    let robot = paint(program, robot::BLACK, options.stats);
+
+    return robot.hull().painted();
+}
+
+fn part2(program: &Vec<i64>, options: &Options) -> String {
+    let robot = paint(program, robot::WHITE, options.stats);
+    let painted = robot.hull();
+    export(&robot, options);
+
+    // show the hull if it isn't all letters
+    return match read_hull(painted) {
+        Ok(text) => text,
+        Err(text) => {
+            show(painted);
+            format!("{text:?}")
+        },
+    };
+}
+
+struct Options {
+    stats: bool,                // --stats, how the robot got on
+    image: Option<String>,      // --image FILE, save the part 2 hull (.png, .ppm or .pbm)
+    heat: bool,                 // --heat, show the robot's visits on the image
+    animate: Option<String>,    // --animate FILE, GIF of the part 2 painting
+    every: usize,               // --every MOVES, per animation frame
+    scale: usize,               // --scale PIXELS, per panel
+}
+
+// pixels per panel in pictures
+const DEFAULT_SCALE: usize = 4;
+
+fn read_options(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        stats: false, image: None, heat: false, animate: None, every: 1, scale: DEFAULT_SCALE,
+    };
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--stats" => options.stats = true,
+            "--image" => options.image = Some(args.next().ok_or("--image needs a file")?.clone()),
+            "--heat" => options.heat = true,
+            "--animate" => options.animate = Some(args.next().ok_or("--animate needs a file")?.clone()),
+            "--every" => {
+                let moves = args.next().ok_or("--every needs moves per frame")?;
+                options.every = moves.parse().ok().filter(|&n| n > 0).ok_or(format!("Bad moves per frame {moves}"))?;
+            },
+            "--scale" => {
+                let pixels = args.next().ok_or("--scale needs pixels per panel")?;
+                options.scale = pixels.parse().ok().filter(|&n| n > 0).ok_or(format!("Bad scale {pixels}"))?;
+            },
+            _ => return Err(format!("Unknown option {arg}")),
+        }
+    }
+
+    return Ok(options);
 }
 
 fn main() {
@@ -114,6 +157,11 @@ fn main() {
         process::exit(1);
     }
 
+    let options = read_options(&args[2..]).unwrap_or_else(|error| {
+        println!("ERROR: {error}");
+        process::exit(1);
+    });
+
     let start = Instant::now();
 
     let data = read_data(&args[1]);
@@ -123,14 +171,14 @@ fn main() {
     );
 
     let p1_start = Instant::now();
-    let p1 = part1(&data);
+    let p1 = part1(&data, &options);
     println!(
         "{p1:>15} ({:9.9} ns)",
         Instant::now().duration_since(p1_start).as_nanos()
     );
 
     let p2_start = Instant::now();
-    let p2 = part2(&data);
+    let p2 = part2(&data, &options);
 
     println!(
         "{p2:>15} ({:9.9} ns)",
@@ -0,0 +1,114 @@
+use std::io;
+use std::collections::HashMap;
+use crate::image::{Gif, Image, Rgb};
+use crate::robot::{self, Config, Hull, Move, Point};
+
+// Pictures of the hull: the final hull as an image (optionally with the
+// robot's visits as a heatmap on top) and an animated GIF of the painting
+
+const BLACK: Rgb = [0, 0, 0];
+const WHITE: Rgb = [255, 255, 255];
+const ROBOT: Rgb = [255, 64, 0];
+const HEAT: Rgb = [255, 0, 0];
+
+// hundredths of a second per animation frame
+const GIF_DELAY: u16 = 2;
+
+fn colour(panel: i64) -> Rgb {
+    return if panel == robot::WHITE { WHITE } else { BLACK };
+}
+
+// mix a towards b, amount from 0 to 1
+fn blend(a: Rgb, b: Rgb, amount: f64) -> Rgb {
+    let mix = |i: usize| (a[i] as f64 + (b[i] as f64 - a[i] as f64) * amount).round() as u8;
+    return [mix(0), mix(1), mix(2)];
+}
+
+// corners of everything coloured or visited
+pub fn area(hull: &Hull, visits: &HashMap<Point, usize>) -> (Point, Point) {
+    let corners = hull.bounds().into_iter().flat_map(|(low, high)| [low, high]);
+    let points: Vec<Point> = corners.chain(visits.keys().copied()).collect();
+
+    let low = Point {
+        x: points.iter().map(|p| p.x).min().unwrap_or(0),
+        y: points.iter().map(|p| p.y).min().unwrap_or(0),
+    };
+    let high = Point {
+        x: points.iter().map(|p| p.x).max().unwrap_or(0),
+        y: points.iter().map(|p| p.y).max().unwrap_or(0),
+    };
+    return (low, high);
+}
+
+// width and height in pixels of area drawn at scale
+fn size(area: (Point, Point), scale: usize) -> (usize, usize) {
+    let (low, high) = area;
+    let width = (high.x - low.x + 1) as usize;
+    let height = (high.y - low.y + 1) as usize;
+    return (width * scale, height * scale);
+}
+
+fn blank(area: (Point, Point), scale: usize) -> Image {
+    let (width, height) = size(area, scale);
+    return Image::new(width, height, BLACK);
+}
+
+fn fill(image: &mut Image, area: (Point, Point), p: Point, scale: usize, rgb: Rgb) {
+    let (low, _high) = area;
+    image.fill((p.x - low.x) as usize, (p.y - low.y) as usize, scale, rgb);
+}
+
+// the hull, each panel scale x scale pixels. With visits each visited panel
+// is tinted, more visits more red
+pub fn hull(hull: &Hull, area: (Point, Point), scale: usize, visits: Option<&HashMap<Point, usize>>) -> Image {
+    let mut image = blank(area, scale);
+
+    let (low, high) = area;
+    let most = visits.and_then(|visits| visits.values().max().copied()).unwrap_or(1);
+
+    for y in low.y..=high.y {
+        for x in low.x..=high.x {
+            let p = Point { x, y };
+            let mut rgb = colour(hull.colour(p));
+
+            if let Some(&count) = visits.and_then(|visits| visits.get(&p)) {
+                rgb = blend(rgb, HEAT, 0.3 + 0.6 * count as f64 / most as f64);
+            }
+
+            fill(&mut image, area, p, scale, rgb);
+        }
+    }
+
+    return image;
+}
+
+// paint the moves one after the other, a frame every `every` moves and one
+// at the end, with the robot drawn where it is
+pub fn animate(config: &Config, path: &[Move], area: (Point, Point), scale: usize, every: usize, filename: &str) -> io::Result<()> {
+    let (width, height) = size(area, scale);
+    let mut gif = Gif::new(width, height, &[BLACK, WHITE, ROBOT], GIF_DELAY)?;
+    let mut image = blank(area, scale);
+
+    fill(&mut image, area, config.start, scale, colour(config.colour));
+    let mut robot = config.start;
+
+    let every = every.max(1);
+    for (i, step) in path.iter().enumerate() {
+        fill(&mut image, area, step.position, scale, colour(step.colour));
+        robot = step.to;
+
+        if i % every == 0 {
+            add_frame(&mut gif, &image, area, robot, scale);
+        }
+    }
+    add_frame(&mut gif, &image, area, robot, scale);
+
+    return gif.save(filename);
+}
+
+// the hull so far with the robot on top
+fn add_frame(gif: &mut Gif, image: &Image, area: (Point, Point), robot: Point, scale: usize) {
+    let mut frame = image.clone();
+    fill(&mut frame, area, robot, scale, ROBOT);
+    gif.add(&frame);
+}
@@ -0,0 +1,368 @@
+use std::fmt;
+use std::collections::HashMap;
+use crate::intcode;
+
+// The hull painting robot, driven by an Intcode program
+//
+// Each move the robot gives the program the colour of the panel it is on,
+// the program outputs a colour to paint that panel and which way to turn,
+// then the robot turns and moves forward one panel. y grows downwards.
+
+#[derive(Clone, Copy, Hash, Eq, PartialEq, Debug)]
+pub struct Point {
+    pub x: i64,
+    pub y: i64,
+}
+
+impl Point {
+    fn add(&mut self, other: &Point) {
+        self.x += other.x;
+        self.y += other.y;
+    }
+}
+
+pub const UP: Point = Point { x: 0, y: -1 };
+
+pub const BLACK: i64 = 0;
+pub const WHITE: i64 = 1;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Config {
+    pub start: Point,
+    pub heading: Point,                     // one step forward
+    pub colour: i64,                        // of the starting panel
+    pub left: i64,                          // turn codes
+    pub right: i64,
+    pub bounds: Option<(Point, Point)>,     // corners of the hull, unbounded if None
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        return Config {
+            start: Point { x: 0, y: 0 },
+            heading: UP,
+            colour: BLACK,
+            left: 0,
+            right: 1,
+            bounds: None,
+        };
+    }
+}
+
+// Ways the program can break the painting protocol
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RobotError {
+    NoColour,               // program wanted input before giving a colour
+    NoTurn(i64),            // program gave a colour (this one) but no turn
+    BadTurn(i64),           // turn code that is neither left nor right
+    OffHull(Point),         // robot moved off a bounded hull
+    Cpu(intcode::CpuError), // program failed
+}
+
+impl fmt::Display for RobotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            RobotError::NoColour => write!(f, "no output colour"),
+            RobotError::NoTurn(colour) => write!(f, "no output direction after colour {colour}"),
+            RobotError::BadTurn(turn) => write!(f, "unknown turn {turn}"),
+            RobotError::OffHull(p) => write!(f, "robot left the hull at ({}, {})", p.x, p.y),
+            RobotError::Cpu(error) => write!(f, "{error}"),
+        };
+    }
+}
+
+// Panel colours and how often each panel was painted
+#[derive(Clone, Debug, Default)]
+pub struct Hull {
+    panels: HashMap<Point, i64>,
+    paints: HashMap<Point, usize>,
+}
+
+impl Hull {
+    pub fn colour(&self, position: Point) -> i64 {
+        return self.panels.get(&position).copied().unwrap_or(BLACK);
+    }
+
+    // colour a panel without painting it, e.g. the starting panel
+    pub fn set(&mut self, position: Point, colour: i64) {
+        self.panels.insert(position, colour);
+    }
+
+    pub fn paint(&mut self, position: Point, colour: i64) {
+        self.panels.insert(position, colour);
+        *self.paints.entry(position).or_insert(0) += 1;
+    }
+
+    // times a panel was painted
+    #[allow(dead_code)]
+    pub fn paints(&self, position: Point) -> usize {
+        return self.paints.get(&position).copied().unwrap_or(0);
+    }
+
+    // panels painted at least once
+    pub fn painted(&self) -> usize {
+        return self.paints.len();
+    }
+
+    // paints over a panel that was already painted
+    pub fn repaints(&self) -> usize {
+        return self.paints.values().map(|count| count - 1).sum();
+    }
+
+    // the panel painted most often, and how often
+    pub fn most_painted(&self) -> Option<(Point, usize)> {
+        return self.paints.iter()
+            .max_by_key(|&(p, count)| (count, -p.y, -p.x))
+            .map(|(&p, &count)| (p, count));
+    }
+
+    // every panel given a colour
+    #[allow(dead_code)]
+    pub fn panels(&self) -> impl Iterator<Item = (Point, i64)> + '_ {
+        return self.panels.iter().map(|(&p, &colour)| (p, colour));
+    }
+
+    // smallest and largest corner of the coloured panels
+    pub fn bounds(&self) -> Option<(Point, Point)> {
+        let xs = self.panels.keys().map(|p| p.x);
+        let ys = self.panels.keys().map(|p| p.y);
+
+        return Some((
+            Point { x: xs.clone().min()?, y: ys.clone().min()? },
+            Point { x: xs.max()?, y: ys.max()? },
+        ));
+    }
+}
+
+// One move of the robot
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Move {
+    pub position: Point,    // panel painted
+    pub colour: i64,
+    pub turn: i64,          // code as output by the program
+    pub heading: Point,     // after turning
+    pub to: Point,          // panel moved to
+}
+
+pub struct Robot {
+    cpu: intcode::CPU,
+    config: Config,
+    hull: Hull,
+    position: Point,
+    heading: Point,
+    path: Vec<Move>,
+}
+
+impl Robot {
+    pub fn new(program: &[i64], config: Config) -> Self {
+        let mut hull = Hull::default();
+        hull.set(config.start, config.colour);
+
+        return Robot {
+            cpu: intcode::build_cpu(program, &[]),
+            config,
+            hull,
+            position: config.start,
+            heading: config.heading,
+            path: Vec::new(),
+        };
+    }
+
+    // paint, turn and move once. None when the program has finished
+    pub fn step(&mut self) -> Result<Option<Move>, RobotError> {
+        if self.cpu.is_terminated() {
+            return match self.cpu.error() {
+                Some(error) => Err(RobotError::Cpu(error)),
+                None => Ok(None),
+            };
+        }
+
+        self.cpu.push_input(self.colour());
+        self.cpu.run();
+
+        let Some(colour) = self.cpu.pop_output() else {
+            return match (self.cpu.error(), self.cpu.is_terminated()) {
+                (Some(error), _) => Err(RobotError::Cpu(error)),
+                (None, true) => Ok(None),
+                (None, false) => Err(RobotError::NoColour),
+            };
+        };
+        let turn = self.cpu.pop_output().ok_or(RobotError::NoTurn(colour))?;
+
+        return self.apply(colour, turn).map(Some);
+    }
+
+    // paint the current panel, turn and move forward, as told by the
+    // program's output. A move off the hull is still made and recorded
+    pub fn apply(&mut self, colour: i64, turn: i64) -> Result<Move, RobotError> {
+        let (dx, dy) = (self.heading.x, self.heading.y);
+        self.heading = match turn {
+            t if t == self.config.left => Point { x: dy, y: -dx },
+            t if t == self.config.right => Point { x: -dy, y: dx },
+            _ => return Err(RobotError::BadTurn(turn)),
+        };
+
+        let painted = self.position;
+        self.hull.paint(painted, colour);
+        self.position.add(&self.heading);
+
+        let step = Move {
+            position: painted,
+            colour,
+            turn,
+            heading: self.heading,
+            to: self.position,
+        };
+        self.path.push(step);
+
+        if let Some((low, high)) = self.config.bounds {
+            let p = self.position;
+            if p.x < low.x || p.y < low.y || p.x > high.x || p.y > high.y {
+                return Err(RobotError::OffHull(p));
+            }
+        }
+
+        return Ok(step);
+    }
+
+    // move until the program finishes
+    pub fn run(&mut self) -> Result<(), RobotError> {
+        while self.step()?.is_some() {}
+        return Ok(());
+    }
+
+    pub fn hull(&self) -> &Hull {
+        return &self.hull;
+    }
+
+    // colour of the panel under the robot, the program's next input
+    pub fn colour(&self) -> i64 {
+        return self.hull.colour(self.position);
+    }
+
+    #[allow(dead_code)]
+    pub fn position(&self) -> Point {
+        return self.position;
+    }
+
+    #[allow(dead_code)]
+    pub fn heading(&self) -> Point {
+        return self.heading;
+    }
+
+    pub fn config(&self) -> &Config {
+        return &self.config;
+    }
+
+    // every move so far
+    pub fn path(&self) -> &[Move] {
+        return &self.path;
+    }
+
+    // panels moved, the length of the path
+    pub fn moves(&self) -> usize {
+        return self.path.len();
+    }
+
+    // times the robot stood on each panel, including where it started
+    pub fn visits(&self) -> HashMap<Point, usize> {
+        let mut visits = HashMap::from([(self.config.start, 1)]);
+        for step in &self.path {
+            *visits.entry(step.to).or_insert(0) += 1;
+        }
+        return visits;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LEFT: i64 = 0;
+    const RIGHT: i64 = 1;
+
+    // a robot whose program halts straight away, driven through apply()
+    fn robot(config: Config) -> Robot {
+        return Robot::new(&[99], config);
+    }
+
+    // (colour read, colour painted, turn) for each move
+    fn drive(robot: &mut Robot, script: &[(i64, i64, i64)]) {
+        for (i, &(input, colour, turn)) in script.iter().enumerate() {
+            assert_eq!(robot.colour(), input, "input of move {i}");
+            robot.apply(colour, turn).unwrap();
+        }
+    }
+
+    #[test]
+    fn paints_the_example() {
+        let mut robot = robot(Config::default());
+        drive(&mut robot, &[
+            (BLACK, WHITE, LEFT),
+            (BLACK, BLACK, LEFT),
+            (BLACK, WHITE, LEFT),
+            (BLACK, WHITE, LEFT),
+            (WHITE, BLACK, RIGHT),
+            (BLACK, WHITE, LEFT),
+            (BLACK, WHITE, LEFT),
+        ]);
+
+        let hull = robot.hull();
+        assert_eq!(hull.painted(), 6);
+        assert_eq!(hull.repaints(), 1);
+        assert_eq!(hull.paints(Point { x: 0, y: 0 }), 2);
+        assert_eq!(hull.colour(Point { x: 0, y: 0 }), BLACK);
+        assert_eq!(robot.position(), Point { x: 0, y: -1 });
+        assert_eq!(robot.heading(), Point { x: -1, y: 0 });
+        assert_eq!(robot.moves(), 7);
+        assert_eq!(robot.visits()[&Point { x: 0, y: 0 }], 2);
+        assert_eq!(robot.step(), Ok(None));
+    }
+
+    #[test]
+    fn starts_on_a_coloured_panel() {
+        let config = Config { start: Point { x: 3, y: 4 }, heading: Point { x: 1, y: 0 }, colour: WHITE, left: 7, right: 8, ..Config::default() };
+        let mut robot = robot(config);
+        drive(&mut robot, &[(WHITE, WHITE, 8), (BLACK, BLACK, 7)]);
+
+        // the starting panel is coloured, not painted
+        assert_eq!(robot.hull().painted(), 2);
+        assert_eq!(robot.path()[0], Move {
+            position: Point { x: 3, y: 4 },
+            colour: WHITE,
+            turn: 8,
+            heading: Point { x: 0, y: 1 },
+            to: Point { x: 3, y: 5 },
+        });
+        assert_eq!(robot.position(), Point { x: 4, y: 5 });
+    }
+
+    #[test]
+    fn bad_turn_changes_nothing() {
+        let mut robot = robot(Config::default());
+        assert_eq!(robot.apply(WHITE, 2), Err(RobotError::BadTurn(2)));
+
+        assert_eq!(robot.hull().painted(), 0);
+        assert_eq!(robot.moves(), 0);
+        assert_eq!(robot.position(), Point { x: 0, y: 0 });
+        assert_eq!(robot.heading(), UP);
+    }
+
+    #[test]
+    fn off_hull_move_is_recorded() {
+        let bounds = Some((Point { x: 0, y: 0 }, Point { x: 1, y: 1 }));
+        let mut robot = robot(Config { bounds, ..Config::default() });
+        drive(&mut robot, &[(BLACK, WHITE, RIGHT)]);
+
+        let off = Point { x: 1, y: -1 };
+        assert_eq!(robot.apply(WHITE, LEFT), Err(RobotError::OffHull(off)));
+
+        // the panel painted and the move agree
+        assert_eq!(robot.hull().paints(Point { x: 1, y: 0 }), 1);
+        assert_eq!(robot.moves(), 2);
+        assert_eq!(robot.path()[1].position, Point { x: 1, y: 0 });
+        assert_eq!(robot.path()[1].to, off);
+        assert_eq!(robot.position(), off);
+        assert_eq!(robot.visits().values().sum::<usize>(), 3);
+    }
+}
@@ -0,0 +1,782 @@
+use std::io;
+use std::fmt::Write;
+use std::io::Write as _;
+use crate::intcode::{self, CpuError};
+use crate::image::{self, Image};
+use crate::display::{Display, Palette, Style};
+
+// tile values the game draws
+pub const EMPTY: i64 = 0;
+pub const WALL: i64 = 1;
+pub const BLOCK: i64 = 2;
+pub const PADDLE: i64 = 3;
+pub const BALL: i64 = 4;
+
+// not a game tile, where the ball is predicted to land
+const MARK: i64 = -1;
+
+// how the tiles look on the terminal and in images
+pub fn palette() -> Palette {
+    return Palette::new(Style::new(' ', "\x1b[0m", [0, 0, 0]))
+        .with(EMPTY, Style::new(' ', "\x1b[0m", [0, 0, 0]))
+        .with(WALL, Style::new('#', "\x1b[0m\x1b[40m", [128, 128, 128]))
+        .with(BLOCK, Style::new('B', "\x1b[0;37m\x1b[41m", [204, 0, 0]))
+        .with(PADDLE, Style::new('P', "\x1b[0;97m\x1b[42m", [0, 170, 0]))
+        .with(BALL, Style::new('O', "\x1b[1;93m\x1b[40m", [255, 255, 85]))
+        .with(MARK, Style::new('X', "\x1b[1;95m\x1b[40m", [255, 85, 255]));
+}
+
+// the game writes the score as a tile at this position
+pub const SCORE: (i64, i64) = (-1, 0);
+
+// What a single output triple (or the end of the program) meant
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GameEvent {
+    Tile { x: i64, y: i64, tile: i64 },                         // any tile drawn
+    Score(i64),
+    BallMoved { from: Option<(i64, i64)>, to: (i64, i64) },     // from where it was last drawn
+    BlockDestroyed { x: i64, y: i64 },
+    GameOver,                                                   // halted with blocks left
+    Won,                                                        // halted with all blocks gone
+}
+
+// The game's display plus what the tiles on it mean
+#[derive(Clone, Debug)]
+pub struct Screen {
+    display: Display,
+    blocks: usize,                  // block tiles on screen
+    ball: Option<(i64, i64)>,       // where the ball was last drawn
+    paddle: Option<(i64, i64)>,
+}
+
+impl Screen {
+    pub fn create() -> Self {
+        return Screen {
+            display: Display::new(EMPTY).with_special(SCORE.0, SCORE.1),
+            blocks: 0,
+            ball: None,
+            paddle: None,
+        };
+    }
+
+    // apply one output triple, returning what it meant
+    pub fn draw(&mut self, x: i64, y: i64, value: i64) -> Vec<GameEvent> {
+        let drawn = self.display.draw(x, y, value);
+        if drawn.special {
+            return vec![GameEvent::Score(value)];
+        }
+
+        let old = drawn.old;
+        let mut events = vec![GameEvent::Tile { x, y, tile: value }];
+
+        if old == BLOCK && value != BLOCK {
+            self.blocks -= 1;
+            events.push(GameEvent::BlockDestroyed { x, y });
+        } else if old != BLOCK && value == BLOCK {
+            self.blocks += 1;
+        }
+
+        if value == BALL {
+            events.push(GameEvent::BallMoved { from: self.ball, to: (x, y) });
+            self.ball = Some((x, y));
+        }
+
+        if value == PADDLE {
+            self.paddle = Some((x, y));
+        } else if self.paddle == Some((x, y)) {
+            self.paddle = None;
+        }
+
+        return events;
+    }
+
+    pub fn get(&self, x: i64, y: i64) -> i64 {
+        return self.display.get(x, y);
+    }
+
+    pub fn display(&self) -> &Display {
+        return &self.display;
+    }
+
+    pub fn find(&self, value: i64) -> Option<(i64, i64)> {
+        return self.display.find(value);
+    }
+
+    pub fn ball(&self) -> Option<(i64, i64)> {
+        return self.ball;
+    }
+
+    pub fn paddle(&self) -> Option<(i64, i64)> {
+        return self.paddle;
+    }
+
+    pub fn score(&self) -> i64 {
+        return self.display.special(SCORE.0, SCORE.1).unwrap_or(0);
+    }
+
+    pub fn blocks(&self) -> usize {
+        return self.blocks;
+    }
+
+    // every tile drawn, sorted by position
+    pub fn tiles(&self) -> Vec<((i64, i64), i64)> {
+        let mut tiles: Vec<((i64, i64), i64)> = self.display.cells()
+            .chain(self.display.outside())
+            .map(|(x, y, value)| ((x, y), value))
+            .collect();
+        tiles.sort();
+        return tiles;
+    }
+
+    pub fn show(&self) {
+        self.show_landing(None);
+    }
+
+    // show with the predicted landing spot of the ball marked
+    pub fn show_landing(&self, landing: Option<&Landing>) {
+        print!("{}", self.ansi(landing));
+    }
+
+    // the whole screen in colour, clearing the terminal first
+    fn ansi(&self, landing: Option<&Landing>) -> String {
+        let overlay = self.overlay(landing);
+        let screen = self.display.ansi(&palette(), &overlay);
+        return format!("\x1b[2J\x1b[H{}\n{screen}\n\x1b[0m", self.header(landing));
+    }
+
+    // the score line above the screen
+    fn header(&self, landing: Option<&Landing>) -> String {
+        let score = self.score();
+        return match landing {
+            Some(landing) => format!("Score: {score}  Landing: x {} in {} frames", landing.x, landing.frames),
+            None => format!("Score: {score}"),
+        };
+    }
+
+    // the landing spot is marked on an empty tile
+    fn overlay(&self, landing: Option<&Landing>) -> Vec<((i64, i64), i64)> {
+        return landing
+            .map(|landing| (landing.x, landing.y))
+            .filter(|&(x, y)| self.get(x, y) == EMPTY)
+            .map(|position| vec![(position, MARK)])
+            .unwrap_or_default();
+    }
+
+    // plain text picture of the screen, no escape sequences
+    pub fn text(&self) -> String {
+        return format!("Score: {}\n{}", self.score(), self.display.text(&palette(), &[]));
+    }
+
+    // picture of the screen with each tile drawn as a scale x scale square
+    pub fn image(&self, palette: &Palette, scale: usize) -> Image {
+        return self.display.image(palette, scale);
+    }
+
+    // the ball has got past the paddle (or reached the bottom row)
+    pub fn will_end(&self) -> bool {
+        let ((_, top), _, height) = self.display.area();
+        let bottom = top + height as i64 - 1;
+        return match (self.ball, self.paddle) {
+            (Some((_, ball)), Some((_, paddle))) => ball > paddle || ball >= bottom,
+            (Some((_, ball)), None) => ball >= bottom,
+            _ => false,
+        };
+    }
+}
+
+// What happened on screen while the game ran up to its next input
+#[derive(Clone, Debug, PartialEq)]
+pub struct Frame {
+    pub number: usize,
+    pub changed: Vec<((i64, i64), i64)>,  // tiles drawn, in output order
+    pub events: Vec<GameEvent>,           // what the drawing meant, in order
+    pub score: i64,
+    pub ball: Option<(i64, i64)>,
+    pub paddle: Option<(i64, i64)>,
+    pub blocks: usize,                    // left to destroy
+    pub lives: u32,
+    pub over: bool,                       // program has halted
+    pub landing: Option<Landing>,         // only if the arcade is predicting
+}
+
+// Where the ball will next come down to the paddle, if the paddle is not
+// moved: the spot just above the paddle row and how many frames away
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Landing {
+    pub x: i64,
+    pub y: i64,
+    pub frames: usize,
+}
+
+// how far ahead predict() looks before giving up
+const PREDICT_FRAMES: usize = 1000;
+
+// Draws frames somewhere; picked by the host
+pub trait Renderer {
+    fn render(&mut self, screen: &Screen, frame: &Frame);
+
+    // called once the game is over, for renderers that write at the end
+    fn finish(&mut self) {
+    }
+}
+
+// headless, draws nothing
+pub struct NoRenderer;
+
+impl Renderer for NoRenderer {
+    fn render(&mut self, _screen: &Screen, _frame: &Frame) {
+    }
+}
+
+// draws on the terminal with ANSI colours. The screen is drawn whole the
+// first time (and when it changes size), after that only the cells that
+// differ from what is on the terminal are redrawn
+pub struct AnsiRenderer {
+    palette: Palette,
+    drawn: Vec<i64>,        // cells as last drawn, row by row
+    area: ((i64, i64), usize, usize),
+    header: String,
+}
+
+impl Default for AnsiRenderer {
+    fn default() -> Self {
+        return AnsiRenderer { palette: palette(), drawn: Vec::new(), area: ((0, 0), 0, 0), header: String::new() };
+    }
+}
+
+impl AnsiRenderer {
+    // what to print for a frame: the whole screen or only what changed
+    fn redraw(&mut self, screen: &Screen, frame: &Frame) -> String {
+        let landing = frame.landing.as_ref();
+        let overlay = screen.overlay(landing);
+        let display = screen.display();
+
+        if display.area() != self.area {
+            self.area = display.area();
+            self.drawn = display.cells().map(|(x, y, _value)| display.shown(x, y, &overlay)).collect();
+            self.header = screen.header(landing);
+            return screen.ansi(landing);
+        }
+
+        let mut raw = String::new();
+
+        let header = screen.header(landing);
+        if header != self.header {
+            write!(raw, "\x1b[H\x1b[0m{header}\x1b[K").expect("failed");
+            self.header = header;
+        }
+
+        let ((left, top), _width, height) = self.area;
+        for ((x, y, _value), drawn) in display.cells().zip(self.drawn.iter_mut()) {
+            let cell = display.shown(x, y, &overlay);
+            if cell != *drawn {
+                // rows and columns start at 1 and the header is on the first
+                let style = self.palette.style(cell);
+                write!(raw, "\x1b[{};{}H{}{}", y - top + 2, x - left + 1, style.ansi, style.glyph).expect("failed");
+                *drawn = cell;
+            }
+        }
+
+        // park the cursor under the screen, clearing any messages there
+        write!(raw, "\x1b[0m\x1b[{};1H\x1b[J", height + 2).expect("failed");
+        return raw;
+    }
+}
+
+impl Renderer for AnsiRenderer {
+    fn render(&mut self, screen: &Screen, frame: &Frame) {
+        print!("{}", self.redraw(screen, frame));
+        io::stdout().flush().expect("failed to draw");
+    }
+}
+
+// appends each frame as plain text to a writer (file, stdout, ...)
+pub struct TextRenderer<W: io::Write> {
+    pub out: W,
+}
+
+impl<W: io::Write> Renderer for TextRenderer<W> {
+    fn render(&mut self, screen: &Screen, frame: &Frame) {
+        let number = frame.number;
+        write!(self.out, "Frame: {number}\n{}\n", screen.text()).expect("failed to write frame");
+    }
+}
+
+// saves every frame as its own image, PREFIX00001.png, ... (or .ppm)
+pub struct ImageRenderer {
+    pub prefix: String,
+    pub extension: &'static str,
+    pub palette: Palette,
+    pub scale: usize,
+}
+
+impl Renderer for ImageRenderer {
+    fn render(&mut self, screen: &Screen, frame: &Frame) {
+        let filename = format!("{}{:05}.{}", self.prefix, frame.number, self.extension);
+        screen.image(&self.palette, self.scale).save(&filename).expect("failed to write frame");
+    }
+}
+
+// collects every frame into an animated GIF, written by finish()
+pub struct GifRenderer {
+    pub filename: String,
+    pub palette: Palette,
+    pub scale: usize,
+    pub gif: Option<image::Gif>,
+}
+
+// 1/100 s per frame in the GIF
+const GIF_DELAY: u16 = 3;
+
+impl Renderer for GifRenderer {
+    fn render(&mut self, screen: &Screen, _frame: &Frame) {
+        let picture = screen.image(&self.palette, self.scale);
+
+        // the first frame sets the size, the screen is drawn whole at boot
+        let palette = &self.palette;
+        let gif = self.gif.get_or_insert_with(|| {
+            return image::Gif::new(picture.width, picture.height, &palette.colours(), GIF_DELAY)
+                .expect("screen too big for a GIF");
+        });
+        gif.add(&picture);
+    }
+
+    fn finish(&mut self) {
+        if let Some(gif) = &self.gif {
+            gif.save(&self.filename).expect("failed to write animation");
+        }
+    }
+}
+
+// none, ansi, text, or an image file: png:PREFIX, ppm:PREFIX, gif:FILE
+pub fn renderer(name: &str, palette: &Palette, scale: usize) -> Option<Box<dyn Renderer>> {
+    let (kind, path) = name.split_once(':').unwrap_or((name, ""));
+    let palette = palette.clone();
+
+    return match (kind, path) {
+        ("none", "") => Some(Box::new(NoRenderer)),
+        ("ansi", "") => Some(Box::new(AnsiRenderer::default())),
+        ("text", "") => Some(Box::new(TextRenderer { out: io::stdout() })),
+        ("png", _) | ("ppm", _) => Some(Box::new(ImageRenderer {
+            prefix: path.to_string(),
+            extension: if kind == "png" { "png" } else { "ppm" },
+            palette,
+            scale,
+        })),
+        ("gif", path) if !path.is_empty() => Some(Box::new(GifRenderer {
+            filename: path.to_string(), palette, scale, gif: None,
+        })),
+        _ => None,
+    };
+}
+
+// memory patch that puts the quarters in
+pub const FREE_PLAY: (usize, i64) = (0, 2);
+
+// Everything needed to carry on a game from between two frames
+#[derive(Clone, Debug, PartialEq)]
+pub struct Snapshot {
+    pub frame: usize,
+    pub cpu: intcode::CpuState,
+    pub tiles: Vec<((i64, i64), i64)>,    // screen, sorted by position
+}
+
+// What a step changed, see Arcade::step_undoable()
+#[derive(Clone, Debug)]
+pub struct Undo {
+    cpu: intcode::Undo,
+    tiles: Vec<((i64, i64), i64)>,  // old values, in drawing order
+    score: i64,
+    ball: Option<(i64, i64)>,
+    paddle: Option<(i64, i64)>,
+    lives: u32,
+    frames: usize,
+    pub joystick: Option<i64>,      // what the step was given
+}
+
+// The arcade cabinet: the game program plus its screen, stepped one frame
+// (one joystick input) at a time
+#[derive(Clone, Debug)]
+pub struct Arcade {
+    cpu: intcode::CPU,
+    screen: Screen,
+    frames: usize,
+    lives: u32,
+    predicting: bool,
+}
+
+// the game has one ball, losing it ends the game
+pub const LIVES: u32 = 1;
+
+impl Arcade {
+    // load the game, free play puts the quarters in (memory[0] = 2)
+    pub fn new(program: &Vec<i64>, free_play: bool) -> Result<Self, CpuError> {
+        let patches = if free_play { vec![FREE_PLAY] } else { vec![] };
+        return Arcade::patched(program, &patches);
+    }
+
+    fn load(program: &[i64]) -> Self {
+        let cpu = intcode::CPU::load_program(program);
+        return Arcade { cpu, screen: Screen::create(), frames: 0, lives: LIVES, predicting: false };
+    }
+
+    // load the game with memory patched as (address, value)
+    pub fn patched(program: &Vec<i64>, patches: &[(usize, i64)]) -> Result<Self, CpuError> {
+        let mut arcade = Arcade::load(program);
+
+        for &(address, value) in patches {
+            arcade.cpu.set_memory(address, value)?;
+        }
+
+        return Ok(arcade);
+    }
+
+    // pick a game up from a snapshot
+    pub fn restore(program: &Vec<i64>, snapshot: &Snapshot) -> Self {
+        let mut arcade = Arcade::load(program);
+        arcade.cpu.set_state(&snapshot.cpu);
+        arcade.frames = snapshot.frame;
+
+        for &((x, y), value) in &snapshot.tiles {
+            arcade.screen.draw(x, y, value);
+        }
+
+        return arcade;
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        // the score is saved as the tile the game draws it with
+        let mut tiles = vec![(SCORE, self.screen.score())];
+        tiles.extend(self.screen.tiles());
+
+        return Snapshot { frame: self.frames, cpu: self.cpu.state(), tiles };
+    }
+
+    // frames run so far, the boot frame is 1
+    pub fn frame(&self) -> usize {
+        return self.frames;
+    }
+
+    // feed the joystick (-1 left, 0 neutral, 1 right; None for the first
+    // frame) and run until the game wants the next input or ends
+    pub fn step(&mut self, joystick: Option<i64>) -> Frame {
+        return self.advance(joystick, &mut Vec::new());
+    }
+
+    // step() that also returns how to take the frame back with undo()
+    pub fn step_undoable(&mut self, joystick: Option<i64>) -> (Frame, Undo) {
+        let mut undo = Undo {
+            cpu: intcode::Undo::default(),
+            tiles: Vec::new(),
+            score: self.screen.score(),
+            ball: self.screen.ball,
+            paddle: self.screen.paddle,
+            lives: self.lives,
+            frames: self.frames,
+            joystick,
+        };
+
+        self.cpu.start_undo();
+        let frame = self.advance(joystick, &mut undo.tiles);
+        undo.cpu = self.cpu.take_undo().unwrap_or_default();
+
+        return (frame, undo);
+    }
+
+    // go back to before the step that returned undo
+    pub fn undo(&mut self, undo: &Undo) {
+        self.cpu.apply_undo(&undo.cpu);
+
+        for &((x, y), value) in undo.tiles.iter().rev() {
+            self.screen.draw(x, y, value);
+        }
+        self.screen.display.draw(SCORE.0, SCORE.1, undo.score);
+        self.screen.ball = undo.ball;
+        self.screen.paddle = undo.paddle;
+
+        self.lives = undo.lives;
+        self.frames = undo.frames;
+    }
+
+    // run a frame, noting the old value of every tile drawn
+    fn advance(&mut self, joystick: Option<i64>, old: &mut Vec<((i64, i64), i64)>) -> Frame {
+        if let Some(joystick) = joystick {
+            self.cpu.push_input(joystick);
+        }
+
+        let running = !self.cpu.is_terminated();
+        self.cpu.run();
+
+        let mut changed = Vec::new();
+        let mut events = Vec::new();
+        for tile in self.cpu.output().chunks(3).filter(|tile| tile.len() == 3) {
+            if (tile[0], tile[1]) != SCORE {
+                old.push(((tile[0], tile[1]), self.screen.get(tile[0], tile[1])));
+            }
+            events.extend(self.screen.draw(tile[0], tile[1], tile[2]));
+            changed.push(((tile[0], tile[1]), tile[2]));
+        }
+
+        if running && self.cpu.is_terminated() {
+            if self.screen.blocks() == 0 {
+                events.push(GameEvent::Won);
+            } else {
+                self.lives = self.lives.saturating_sub(1);
+                events.push(GameEvent::GameOver);
+            }
+        }
+
+        self.frames += 1;
+        return Frame { changed, events, ..self.status() };
+    }
+
+    // the current frame as it stands, with nothing newly drawn
+    pub fn status(&self) -> Frame {
+        return Frame {
+            number: self.frames,
+            changed: Vec::new(),
+            events: Vec::new(),
+            score: self.screen.score(),
+            ball: self.screen.ball(),
+            paddle: self.screen.paddle(),
+            blocks: self.screen.blocks(),
+            lives: self.lives,
+            over: self.cpu.is_terminated(),
+            landing: if self.predicting { self.predict() } else { None },
+        };
+    }
+
+    // fill in Frame::landing on every frame (runs the game ahead each time)
+    pub fn set_predicting(&mut self, predicting: bool) {
+        self.predicting = predicting;
+    }
+
+    // run a copy of the game forward with the joystick neutral until the
+    // ball is moving down into the row above the paddle. A ball already in
+    // that row is landing now (0 frames). None if the game ends first or it
+    // takes too long
+    pub fn predict(&self) -> Option<Landing> {
+        if let (Some((x, y)), Some((_, paddle))) = (self.screen.ball(), self.screen.paddle()) && y == paddle - 1 {
+            return Some(Landing { x, y, frames: 0 });
+        }
+
+        let mut fork = self.fork();
+        fork.predicting = false;
+
+        for frames in 1..=PREDICT_FRAMES {
+            if fork.is_over() {
+                return None;
+            }
+
+            let frame = fork.step(Some(0));
+            let landed = frame.events.iter().any(|event| match (event, frame.paddle) {
+                (GameEvent::BallMoved { from: Some((_, from)), to: (_, to) }, Some((_, paddle))) => {
+                    to > from && *to == paddle - 1
+                },
+                _ => false,
+            });
+
+            if landed {
+                let (x, y) = frame.ball?;
+                return Some(Landing { x, y, frames });
+            }
+        }
+
+        return None;
+    }
+
+    // a copy of the game without the CPU's journal or undo trace
+    pub fn fork(&self) -> Arcade {
+        return Arcade {
+            cpu: self.cpu.fork(),
+            screen: self.screen.clone(),
+            frames: self.frames,
+            lives: self.lives,
+            predicting: self.predicting,
+        };
+    }
+
+    // go back to a fork taken earlier in this game, the journal is kept up
+    // to there
+    pub fn rewind_to(&mut self, fork: &Arcade) {
+        self.cpu.rewind_to(&fork.cpu);
+        self.screen = fork.screen.clone();
+        self.frames = fork.frames;
+        self.lives = fork.lives;
+    }
+
+    pub fn lives(&self) -> u32 {
+        return self.lives;
+    }
+
+    pub fn is_over(&self) -> bool {
+        return self.cpu.is_terminated();
+    }
+
+    pub fn screen(&self) -> &Screen {
+        return &self.screen;
+    }
+
+    pub fn cpu(&mut self) -> &mut intcode::CPU {
+        return &mut self.cpu;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a program that draws each frame's (x, y, tile) triples and then waits
+    // for the joystick, which it writes to memory[0], halting after the last
+    fn script(frames: &[&[(i64, i64, i64)]]) -> Vec<i64> {
+        let mut program = Vec::new();
+        for (i, frame) in frames.iter().enumerate() {
+            if i > 0 {
+                program.extend([3, 0]);
+            }
+            for &(x, y, tile) in frame.iter() {
+                program.extend([104, x, 104, y, 104, tile]);
+            }
+        }
+        program.push(99);
+        return program;
+    }
+
+    // walls at the top corners, a block, the ball and the paddle. The ball
+    // breaks the block, then the paddle moves right and the game ends
+    const BOOT: &[(i64, i64, i64)] = &[
+        (0, 0, WALL), (3, 0, WALL), (2, 1, BLOCK), (2, 2, BALL), (2, 3, PADDLE), (-1, 0, 0),
+    ];
+    const BREAK: &[(i64, i64, i64)] = &[(2, 2, EMPTY), (2, 1, BALL), (-1, 0, 10)];
+    const MOVE: &[(i64, i64, i64)] = &[(2, 3, EMPTY), (3, 3, PADDLE)];
+
+    fn changed(triples: &[(i64, i64, i64)]) -> Vec<((i64, i64), i64)> {
+        return triples.iter().map(|&(x, y, tile)| ((x, y), tile)).collect();
+    }
+
+    #[test]
+    fn steps_frame_by_frame() {
+        let mut arcade = Arcade::new(&script(&[BOOT, BREAK, MOVE]), false).unwrap();
+        assert_eq!(arcade.frame(), 0);
+
+        let boot = arcade.step(None);
+        assert_eq!((boot.number, boot.changed.clone()), (1, changed(BOOT)));
+        assert_eq!((boot.ball, boot.paddle), (Some((2, 2)), Some((2, 3))));
+        assert_eq!((boot.score, boot.blocks, boot.lives, boot.over), (0, 1, LIVES, false));
+        assert_eq!(arcade.status(), Frame { changed: Vec::new(), events: Vec::new(), ..boot });
+
+        let broken = arcade.step(Some(-1));
+        assert_eq!((broken.number, broken.changed), (2, changed(BREAK)));
+        assert_eq!((broken.ball, broken.score, broken.blocks, broken.over), (Some((2, 1)), 10, 0, false));
+        assert_eq!(arcade.cpu().get_memory(0), -1);
+
+        let end = arcade.step(Some(1));
+        assert_eq!((end.number, end.changed), (3, changed(MOVE)));
+        assert_eq!((end.paddle, end.over), (Some((3, 3)), true));
+        assert_eq!(arcade.cpu().get_memory(0), 1);
+        assert!(arcade.is_over());
+
+        assert_eq!(arcade.screen().text(), "Score: 10\n#  #\n  O \n    \n   P\n");
+    }
+
+    #[test]
+    fn events_from_scripted_frames() {
+        let mut arcade = Arcade::new(&script(&[BOOT, BREAK, MOVE]), false).unwrap();
+
+        let boot = arcade.step(None);
+        assert_eq!(boot.events, vec![
+            GameEvent::Tile { x: 0, y: 0, tile: WALL },
+            GameEvent::Tile { x: 3, y: 0, tile: WALL },
+            GameEvent::Tile { x: 2, y: 1, tile: BLOCK },
+            GameEvent::Tile { x: 2, y: 2, tile: BALL },
+            GameEvent::BallMoved { from: None, to: (2, 2) },
+            GameEvent::Tile { x: 2, y: 3, tile: PADDLE },
+            GameEvent::Score(0),
+        ]);
+
+        assert_eq!(arcade.step(Some(0)).events, vec![
+            GameEvent::Tile { x: 2, y: 2, tile: EMPTY },
+            GameEvent::Tile { x: 2, y: 1, tile: BALL },
+            GameEvent::BlockDestroyed { x: 2, y: 1 },
+            GameEvent::BallMoved { from: Some((2, 2)), to: (2, 1) },
+            GameEvent::Score(10),
+        ]);
+
+        let end = arcade.step(Some(0));
+        assert_eq!(end.events, vec![
+            GameEvent::Tile { x: 2, y: 3, tile: EMPTY },
+            GameEvent::Tile { x: 3, y: 3, tile: PADDLE },
+            GameEvent::Won,
+        ]);
+        assert_eq!(end.lives, LIVES);
+
+        // nothing more happens once the game is over
+        assert_eq!(arcade.step(Some(0)).events, vec![]);
+    }
+
+    #[test]
+    fn game_over_with_blocks_left() {
+        let mut arcade = Arcade::new(&script(&[BOOT, &[(2, 2, EMPTY), (2, 4, BALL)]]), false).unwrap();
+        arcade.step(None);
+
+        let end = arcade.step(Some(0));
+        assert_eq!(end.events.last(), Some(&GameEvent::GameOver));
+        assert_eq!((end.blocks, end.lives, end.over), (1, 0, true));
+        assert!(arcade.screen().will_end());
+    }
+
+    // the (row, column) of every cursor move in ANSI output
+    fn cursor_moves(raw: &str) -> Vec<(i64, i64)> {
+        return raw.split("\x1b[")
+            .filter_map(|sequence| sequence.split_once('H'))
+            .filter_map(|(position, _rest)| position.split_once(';'))
+            .map(|(row, column)| (row.parse().unwrap(), column.parse().unwrap()))
+            .collect();
+    }
+
+    #[test]
+    fn redraws_only_changed_cells() {
+        let mut arcade = Arcade::new(&script(&[BOOT, BREAK, &[(2, 3, PADDLE)], &[(3, 4, WALL)]]), false).unwrap();
+        let mut renderer = AnsiRenderer::default();
+
+        let frame = arcade.step(None);
+        let whole = renderer.redraw(arcade.screen(), &frame);
+        assert!(whole.starts_with("\x1b[2J\x1b[HScore: 0\n"));
+
+        // the ball moved from (2, 2) to (2, 1), redrawn row by row a row down
+        // for the score, then the cursor is parked under the 4 rows
+        let frame = arcade.step(Some(0));
+        let raw = renderer.redraw(arcade.screen(), &frame);
+        assert_eq!(cursor_moves(&raw), vec![(3, 3), (4, 3), (6, 1)]);
+        assert!(raw.contains("Score: 10\x1b[K"));
+        assert!(!raw.contains("\x1b[2J"));
+
+        // drawing the paddle where it was changes nothing
+        let frame = arcade.step(Some(0));
+        assert_eq!(frame.changed, vec![((2, 3), PADDLE)]);
+        let raw = renderer.redraw(arcade.screen(), &frame);
+        assert_eq!(cursor_moves(&raw), vec![(6, 1)]);
+        assert!(!raw.contains("Score"));
+
+        // the screen grew, so it is drawn whole again
+        let frame = arcade.step(Some(0));
+        assert!(renderer.redraw(arcade.screen(), &frame).starts_with("\x1b[2J"));
+    }
+
+    #[test]
+    fn paddle_and_blocks_follow_the_tiles() {
+        let mut screen = Screen::create();
+        screen.draw(1, 1, BLOCK);
+        screen.draw(2, 1, BLOCK);
+        assert_eq!(screen.draw(1, 1, BLOCK), vec![GameEvent::Tile { x: 1, y: 1, tile: BLOCK }]);
+        assert_eq!(screen.blocks(), 2);
+
+        screen.draw(3, 5, PADDLE);
+        assert_eq!(screen.paddle(), Some((3, 5)));
+        screen.draw(3, 5, EMPTY);
+        assert_eq!(screen.paddle(), None);
+
+        assert_eq!(screen.draw(SCORE.0, SCORE.1, 42), vec![GameEvent::Score(42)]);
+        assert_eq!((screen.score(), screen.get(SCORE.0, SCORE.1)), (42, EMPTY));
+    }
+}
@@ -0,0 +1,278 @@
+use std::fmt::Write;
+use std::collections::{BTreeMap, HashMap};
+use crate::image::{Image, Rgb};
+
+// A 2D tile display for Intcode programs that draw with (x, y, value)
+// output triples
+//
+// The grid grows as far as the program draws in any direction, up to
+// MAX_CELLS. Cells drawn further out than that (a stray coordinate) are
+// kept in a map aside and not shown. Special coordinates (like day 13's
+// score at (-1, 0)) are not drawn on the grid, their values are kept
+// aside. What each value looks like comes from a Palette, so the same
+// display can be shown as plain text, in colour on a terminal or saved as
+// an image.
+
+// most cells the grid grows to
+pub const MAX_CELLS: usize = 1 << 20;
+
+// How one value is drawn
+#[derive(Clone, Debug, PartialEq)]
+pub struct Style {
+    pub glyph: char,
+    pub ansi: String,   // escape sequence set before the glyph
+    pub rgb: Rgb,
+}
+
+impl Style {
+    pub fn new(glyph: char, ansi: &str, rgb: Rgb) -> Self {
+        return Style { glyph, ansi: ansi.to_string(), rgb };
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Palette {
+    styles: BTreeMap<i64, Style>,
+    fallback: Style,    // for values without a style
+}
+
+impl Palette {
+    pub fn new(fallback: Style) -> Self {
+        return Palette { styles: BTreeMap::new(), fallback };
+    }
+
+    pub fn with(mut self, value: i64, style: Style) -> Self {
+        self.styles.insert(value, style);
+        return self;
+    }
+
+    pub fn style(&self, value: i64) -> &Style {
+        return self.styles.get(&value).unwrap_or(&self.fallback);
+    }
+
+    // every colour used, the fallback first
+    pub fn colours(&self) -> Vec<Rgb> {
+        let mut colours = vec![self.fallback.rgb];
+        colours.extend(self.styles.values().map(|style| style.rgb));
+        return colours;
+    }
+
+    // change image colours with "VALUE=RRGGBB,...", e.g. "0=ffffff,1=000000"
+    pub fn parse_colours(&mut self, text: &str) -> Result<(), String> {
+        for entry in text.split(',') {
+            let bad = || format!("Bad palette entry {entry}, expected VALUE=RRGGBB");
+            let (value, hex) = entry.split_once('=').ok_or_else(bad)?;
+            let value: i64 = value.trim().parse().map_err(|_| bad())?;
+            let rgb = u32::from_str_radix(hex.trim(), 16).map_err(|_| bad())?;
+            if hex.trim().len() != 6 {
+                return Err(bad());
+            }
+
+            let fallback = self.fallback.clone();
+            let style = self.styles.entry(value).or_insert(fallback);
+            style.rgb = [(rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8];
+        }
+
+        return Ok(());
+    }
+}
+
+// One triple after it was applied
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Drawn {
+    pub x: i64,
+    pub y: i64,
+    pub value: i64,
+    pub old: i64,           // what was there before
+    pub special: bool,      // went to a special coordinate, not the grid
+}
+
+#[derive(Clone, Debug)]
+pub struct Display {
+    grid: Vec<i64>,         // row by row
+    left: i64,              // coordinates of grid[0], never right of or
+    top: i64,               // below the origin
+    width: usize,
+    height: usize,
+    background: i64,        // value of cells never drawn
+    special: HashMap<(i64, i64), Option<i64>>,
+    outside: HashMap<(i64, i64), i64>,          // drawn too far out for the grid
+    bounds: Option<((i64, i64), (i64, i64))>,   // of everything drawn, outside too
+}
+
+impl Display {
+    pub fn new(background: i64) -> Self {
+        return Display {
+            grid: Vec::new(),
+            left: 0, top: 0,
+            width: 0, height: 0,
+            background,
+            special: HashMap::new(),
+            outside: HashMap::new(),
+            bounds: None,
+        };
+    }
+
+    // keep values drawn at (x, y) off the grid
+    pub fn with_special(mut self, x: i64, y: i64) -> Self {
+        self.special.insert((x, y), None);
+        return self;
+    }
+
+    pub fn draw(&mut self, x: i64, y: i64, value: i64) -> Drawn {
+        if let Some(special) = self.special.get_mut(&(x, y)) {
+            let old = special.replace(value).unwrap_or(self.background);
+            return Drawn { x, y, value, old, special: true };
+        }
+
+        let old = match self.include(x, y) {
+            Some(i) => std::mem::replace(&mut self.grid[i], value),
+            None => self.outside.insert((x, y), value).unwrap_or(self.background),
+        };
+
+        self.bounds = Some(match self.bounds {
+            Some(((x0, y0), (x1, y1))) => ((x0.min(x), y0.min(y)), (x1.max(x), y1.max(y))),
+            None => ((x, y), (x, y)),
+        });
+
+        return Drawn { x, y, value, old, special: false };
+    }
+
+    pub fn get(&self, x: i64, y: i64) -> i64 {
+        return match self.index(x, y) {
+            Some(i) => self.grid[i],
+            None => self.outside.get(&(x, y)).copied().unwrap_or(self.background),
+        };
+    }
+
+    pub fn special(&self, x: i64, y: i64) -> Option<i64> {
+        return self.special.get(&(x, y)).copied().flatten();
+    }
+
+    // smallest and largest corner of everything drawn
+    #[allow(dead_code)]
+    pub fn bounds(&self) -> Option<((i64, i64), (i64, i64))> {
+        return self.bounds;
+    }
+
+    // the area shown: from the origin (or further up and left, if drawn
+    // there) to the furthest cell drawn
+    pub fn area(&self) -> ((i64, i64), usize, usize) {
+        return ((self.left, self.top), self.width, self.height);
+    }
+
+    // cells in the area row by row, as (x, y, value)
+    pub fn cells(&self) -> impl Iterator<Item = (i64, i64, i64)> + '_ {
+        return self.grid.iter().enumerate().map(|(i, &value)| {
+            let x = self.left + (i % self.width) as i64;
+            let y = self.top + (i / self.width) as i64;
+            return (x, y, value);
+        });
+    }
+
+    // cells drawn too far out to be in the area, as (x, y, value)
+    pub fn outside(&self) -> impl Iterator<Item = (i64, i64, i64)> + '_ {
+        return self.outside.iter().map(|(&(x, y), &value)| (x, y, value));
+    }
+
+    pub fn find(&self, value: i64) -> Option<(i64, i64)> {
+        return self.cells()
+            .chain(self.outside())
+            .find(|&(_x, _y, v)| v == value)
+            .map(|(x, y, _v)| (x, y));
+    }
+
+    fn index(&self, x: i64, y: i64) -> Option<usize> {
+        let (dx, dy) = (x - self.left, y - self.top);
+        if dx < 0 || dy < 0 || dx >= self.width as i64 || dy >= self.height as i64 {
+            return None;
+        }
+        return Some(dy as usize * self.width + dx as usize);
+    }
+
+    // grow the grid to take in (x, y), keeping what is drawn. The index of
+    // (x, y), None if the grid would grow past MAX_CELLS
+    fn include(&mut self, x: i64, y: i64) -> Option<usize> {
+        if let Some(i) = self.index(x, y) {
+            return Some(i);
+        }
+
+        // wide enough not to overflow whatever the coordinates
+        let (x, y) = (x as i128, y as i128);
+        let left = (self.left as i128).min(x);
+        let top = (self.top as i128).min(y);
+        let right = (self.left as i128 + self.width as i128).max(x + 1);
+        let bottom = (self.top as i128 + self.height as i128).max(y + 1);
+        if (right - left) * (bottom - top) > MAX_CELLS as i128 {
+            return None;
+        }
+        let (left, top) = (left as i64, top as i64);
+        let width = (right - left as i128) as usize;
+        let height = (bottom - top as i128) as usize;
+
+        let mut grid = vec![self.background; width * height];
+        for (row, cells) in self.grid.chunks(self.width.max(1)).enumerate() {
+            let start = (row as i64 + self.top - top) as usize * width + (self.left - left) as usize;
+            grid[start..start + cells.len()].copy_from_slice(cells);
+        }
+
+        self.grid = grid;
+        (self.left, self.top, self.width, self.height) = (left, top, width, height);
+
+        // cells kept aside that are in the grid now
+        let inside: Vec<(i64, i64)> = self.outside.keys()
+            .filter(|&&(x, y)| self.index(x, y).is_some())
+            .copied()
+            .collect();
+        for (x, y) in inside {
+            let i = self.index(x, y).expect("just checked");
+            self.grid[i] = self.outside.remove(&(x, y)).expect("just found");
+        }
+
+        return self.index(x as i64, y as i64);
+    }
+
+    // value to show at (x, y), the overlay goes on top of the grid
+    pub fn shown(&self, x: i64, y: i64, overlay: &[((i64, i64), i64)]) -> i64 {
+        return match overlay.iter().find(|(position, _value)| *position == (x, y)) {
+            Some(&(_position, value)) => value,
+            None => self.get(x, y),
+        };
+    }
+
+    // one line of glyphs per row
+    pub fn text(&self, palette: &Palette, overlay: &[((i64, i64), i64)]) -> String {
+        let mut raw = String::new();
+        for y in self.top..self.top + self.height as i64 {
+            for x in self.left..self.left + self.width as i64 {
+                raw.push(palette.style(self.shown(x, y, overlay)).glyph);
+            }
+            raw.push('\n');
+        }
+        return raw;
+    }
+
+    // like text() with each glyph preceded by its escape sequence
+    pub fn ansi(&self, palette: &Palette, overlay: &[((i64, i64), i64)]) -> String {
+        let mut raw = String::new();
+        for y in self.top..self.top + self.height as i64 {
+            for x in self.left..self.left + self.width as i64 {
+                let style = palette.style(self.shown(x, y, overlay));
+                write!(raw, "{}{}", style.ansi, style.glyph).expect("failed");
+            }
+            raw.push('\n');
+        }
+        return raw;
+    }
+
+    // each cell as a scale x scale square
+    pub fn image(&self, palette: &Palette, scale: usize) -> Image {
+        let mut image = Image::new(self.width * scale, self.height * scale, palette.style(self.background).rgb);
+
+        for (i, &value) in self.grid.iter().enumerate() {
+            image.fill(i % self.width, i / self.width, scale, palette.style(value).rgb);
+        }
+
+        return image;
+    }
+}
@@ -0,0 +1,142 @@
+use crate::arcade::{Arcade, Frame, Undo};
+
+// Everything played since a starting frame, so the game can be rewound to
+// any frame after it
+//
+// Each frame keeps an undo delta (the CPU memory cells it wrote, the tiles
+// it drew and the joystick it was given) which is far smaller than a copy
+// of the game. Every `every` frames a whole copy (less the CPU's journal,
+// which only grows) is kept as a keyframe so a long rewind starts from the
+// next keyframe instead of undoing every frame on the way back.
+pub struct History {
+    start: usize,               // frame the history begins at
+    every: usize,
+    undos: Vec<Undo>,           // undos[i] takes frame start + i + 1 back to start + i
+    keyframes: Vec<Arcade>,     // in frame order
+}
+
+impl History {
+    pub fn new(arcade: &Arcade, every: usize) -> Self {
+        return History {
+            start: arcade.frame(),
+            every: every.max(1),
+            undos: Vec::new(),
+            keyframes: Vec::new(),
+        };
+    }
+
+    // step the game, remembering how to undo it
+    pub fn step(&mut self, arcade: &mut Arcade, joystick: i64) -> Frame {
+        let (frame, undo) = arcade.step_undoable(Some(joystick));
+        self.undos.push(undo);
+
+        if arcade.frame().is_multiple_of(self.every) {
+            self.keyframes.push(arcade.fork());
+        }
+
+        return frame;
+    }
+
+    // frames that can be rewound
+    pub fn len(&self) -> usize {
+        return self.undos.len();
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        return self.undos.is_empty();
+    }
+
+    // joystick input since the start
+    #[allow(dead_code)]
+    pub fn keys(&self) -> Vec<i64> {
+        return self.undos.iter().filter_map(|undo| undo.joystick).collect();
+    }
+
+    // go back up to frames frames, no further than the start. Returns how
+    // far it went
+    pub fn rewind(&mut self, arcade: &mut Arcade, frames: usize) -> usize {
+        let frames = frames.min(self.undos.len());
+        let target = arcade.frame() - frames;
+
+        // jump to the first keyframe at or after the target
+        let keyframe = self.keyframes.iter()
+            .find(|keyframe| keyframe.frame() >= target && keyframe.frame() < arcade.frame());
+        if let Some(keyframe) = keyframe {
+            arcade.rewind_to(keyframe);
+            self.undos.truncate(arcade.frame() - self.start);
+        }
+
+        while arcade.frame() > target {
+            let undo = self.undos.pop().expect("history out of step with the game");
+            arcade.undo(&undo);
+        }
+
+        // anything after the target is gone
+        self.keyframes.retain(|keyframe| keyframe.frame() <= target);
+
+        return frames;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arcade::FREE_PLAY;
+    use crate::tests::CATCH;
+
+    // keeps the paddle away from the ball, so the game runs 20 frames
+    const KEYS: [i64; 14] = [1, 1, -1, 0, 1, -1, -1, 0, 1, 1, -1, 0, -1, 1];
+
+    // booted and recording the CPU's journal
+    fn boot(program: &Vec<i64>) -> Arcade {
+        let mut arcade = Arcade::patched(program, &[FREE_PLAY]).unwrap();
+        arcade.cpu().start_recording();
+        arcade.step(None);
+        return arcade;
+    }
+
+    // a fresh game played with keys
+    fn fresh(program: &Vec<i64>, keys: &[i64]) -> Arcade {
+        let mut arcade = boot(program);
+        keys.iter().for_each(|&key| { arcade.step(Some(key)); });
+        return arcade;
+    }
+
+    fn assert_same(arcade: &mut Arcade, expected: &mut Arcade) {
+        assert_eq!(arcade.snapshot(), expected.snapshot());
+        assert_eq!(arcade.status(), expected.status());
+        assert_eq!(arcade.cpu().journal(), expected.cpu().journal());
+    }
+
+    #[test]
+    fn rewinds_across_keyframes() {
+        let program = CATCH.to_vec();
+        let mut arcade = boot(&program);
+        let mut history = History::new(&arcade, 5);
+
+        for &key in &KEYS[..12] {
+            history.step(&mut arcade, key);
+        }
+        assert_eq!((arcade.frame(), history.len()), (13, 12));
+
+        // from 13 back past the keyframes at 10 and 5 to 4
+        assert_eq!(history.rewind(&mut arcade, 9), 9);
+        assert_same(&mut arcade, &mut fresh(&program, &KEYS[..3]));
+        assert_eq!(history.keys(), KEYS[..3]);
+
+        // play on differently, then back within one stretch of keyframes
+        for &key in &KEYS[..5] {
+            history.step(&mut arcade, -key);
+        }
+        let played: Vec<i64> = KEYS[..3].iter().copied().chain(KEYS[..5].iter().map(|key| -key)).collect();
+        assert_same(&mut arcade, &mut fresh(&program, &played));
+        assert_eq!(history.rewind(&mut arcade, 2), 2);
+        assert_same(&mut arcade, &mut fresh(&program, &played[..6]));
+
+        // no further than the start
+        assert_eq!(history.rewind(&mut arcade, 100), 6);
+        assert!(history.is_empty());
+        assert_same(&mut arcade, &mut boot(&program));
+    }
+}
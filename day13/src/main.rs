@@ -1,221 +1,274 @@
 use std::env;
 use std::process;
-use std::fs;
-use std::fmt::Write;
 use std::time::Instant; // 0.8.2
-use std::collections::HashMap;
 pub mod intcode;
+pub mod arcade;
+pub mod terminal;
+pub mod session;
+pub mod image;
+pub mod history;
+pub mod display;
+
+use std::time::Duration;
+use arcade::{Arcade, Renderer};
+use session::Session;
+use history::History;
+use terminal::Key;
 
-#[derive(Clone, Debug)]
-struct Screen {
-    //buffer: Vec<Vec<Pixel>>,
-    buffer: HashMap<(i64, i64), i64>,
-    x_max: i64,
-    y_max: i64,
+fn read_data(filename: &str) -> Vec<i64>  {
+    return intcode::read_program(filename);
 }
 
-impl Screen {
-    fn create() -> Self {
-        return Screen {
-            buffer: HashMap::new(),
-            x_max: 0, y_max: 0,
-        };
-    }
-
-    fn set(&mut self, x: i64, y: i64, value: i64) {
-        // println!("set {x},{y}->{value}");
-        self.buffer.insert((x, y), value);
-
-        self.x_max = x.max(self.x_max);
-        self.y_max = y.max(self.y_max);
-    }
-
-    fn get(&self, x: i64, y: i64) -> i64 {
-        return match self.buffer.get(&(x, y)) {
-            Some(value) => *value,
-            None => 0,
-        };
-    }
-
-    fn show(&self) {
-        let score = self.get(-1, 0);
-        let mut raw = "\x1b[2J\x1b[H".to_string();
-        write!(raw, "Score: {score}\n").expect("failed");
-
-        for y in 0..=self.y_max {
-            for x in 0..=self.x_max {
-                match self.get(x, y) {
-                    1 => write!(raw, "\x1b[0m\x1b[40m#").expect("failed"),
-                    2 => write!(raw, "\x1b[0;37m\x1b[41mB").expect("failed"),
-                    3 => write!(raw, "\x1b[0;97m\x1b[42mP").expect("failed"),
-                    4 => write!(raw, "\x1b[1;93m\x1b[40mO").expect("failed"),
-                    _ => write!(raw, "\x1b[0m ").expect("failed"),
-                };
-            }
-            write!(raw, "\n").expect("failed");
-        }
-        write!(raw, "\n").expect("failed");
-        print!("{raw}\x1b[0m");
-    }
+// the game, or give up if it can't be loaded
+fn load(program: &Vec<i64>, free_play: bool) -> Arcade {
+    return Arcade::new(program, free_play).unwrap_or_else(|error| {
+        println!("ERROR: can't load the game, {error}");
+        process::exit(1);
+    });
+}
 
-    fn will_end(&self) -> bool {
-        for x in 0..=self.x_max {
-            if self.get(x, self.y_max) == 4 {
-                return true;
-            }
-        }
+fn part1(program: &Vec<i64>) -> usize {
+    // without quarters the game just draws the screen and stops
+    let mut arcade = load(program, false);
+    let frame = arcade.step(None);
 
-        return false;
-    }
+    return frame.blocks;
 }
 
-fn read_data(filename: &str) -> Vec<i64>  {
-    return intcode::read_program(filename);
-}
+// replay a journal recorded by interactive(), checking the game produces
+// exactly the same output for the same input. Stops with an error if not
+fn replay(program: &Vec<i64>, filename: &str, renderer: &mut dyn Renderer) -> usize {
+    let mut arcade = load(program, true);
 
-fn part1(program: &Vec<i64>) -> usize {
-    let mut cpu = intcode::CPU::load_program(program);
+    let journal = intcode::Journal::load(filename).unwrap_or_else(|error| {
+        println!("ERROR: {error}");
+        process::exit(1);
+    });
 
-    cpu.run();
+    if let Err(divergence) = intcode::replay(arcade.cpu(), &journal) {
+        println!("ERROR: replay diverged, {divergence}");
+        process::exit(1);
+    }
 
-    // count block tiles (2) that are produced
-    let output = cpu.output();
-    let blocks: Vec<_> = output
-        .chunks(3)
-        .filter(|&threesome| threesome[2] == 2)
-        .collect();
+    // draw everything the replay produced as one frame
+    let frame = arcade.step(None);
+    renderer.render(arcade.screen(), &frame);
 
-    return blocks.len();
+    return arcade.screen().score() as usize;
 }
 
-#[allow(dead_code)]
-fn read_input(filename: &str) -> Vec<i64> {
-    // create vector of points and their value
-    let input = fs::read_to_string(filename)
-        .expect("Expected input file to exist")
-        .split(',')
-        .map(|number| number.parse().unwrap())
-        .collect();
+// frames between full copies of the game kept for rewinding
+const KEYFRAME_EVERY: usize = 500;
 
-    return input;
-}
+// interactive version with "undo" that records the session
+// a or left = left
+// d or right = right
+// s, space or down = nothing
+// w or up = reWind, digits first to rewind that many frames (e.g. 50w)
+// q = quit
+// keys are read one at a time from the raw terminal. With a tick the game
+// moves on (joystick neutral) when no key is pressed in time.
+// play this version until you win, the session is saved to session.txt
+// (with a snapshot every 100 frames) for --replay and --resume. The full
+// I/O of a new session is also saved to journal.txt for --journal
+fn interactive(program: &Vec<i64>, tick: Option<Duration>, predict: bool, resume: Option<Session>) -> usize {
+    let mut renderer = arcade::AnsiRenderer::default();
+    let fresh = resume.is_none();
+    let mut session = resume.unwrap_or_else(|| Session::new(program, vec![arcade::FREE_PLAY], 100));
+
+    // a resumed session carries on from where it ended
+    let end = if fresh { 0 } else { session.frames() };
+    let mut arcade = session.seek(program, end).unwrap_or_else(|error| {
+        println!("ERROR: {error}");
+        process::exit(1);
+    });
+    arcade.set_predicting(predict);
 
-#[allow(dead_code)]
-fn playback(program: &Vec<i64>, filename: &str) -> usize {
-    let mut screen = Screen::create();
-    let mut cpu = intcode::CPU::load_program(program);
+    let keyboard = terminal::Keyboard::open().unwrap_or_else(|error| {
+        println!("ERROR: need a terminal to play ({error})");
+        process::exit(1);
+    });
+
+    if fresh {
+        arcade.cpu().start_recording();
+        let frame = arcade.step(None);
+        renderer.render(arcade.screen(), &frame);
+    } else {
+        renderer.render(arcade.screen(), &arcade.status());
+    }
 
-    let mut keys: Vec<i64> = read_input(filename).into_iter().rev().collect();
+    // rewind goes back as far as this game was played (not into a resumed
+    // session's past), by a count typed before the key or one frame
+    let mut history = History::new(&arcade, KEYFRAME_EVERY);
+    let mut count: usize = 0;
+
+    while !arcade.is_over() {
+        let joystick = match keyboard.wait(tick) {
+            Key::Quit => break,
+            Key::Left => -1,
+            Key::Neutral => 0,
+            Key::Right => 1,
+            Key::Digit(digit) => {
+                count = count.saturating_mul(10).saturating_add(digit as usize);
+                println!("REWIND {count}");
+                continue;
+            },
+            Key::Rewind => {
+                let frames = history.rewind(&mut arcade, count.max(1));
+                session.truncate(arcade.frame());
+                count = 0;
+                renderer.render(arcade.screen(), &arcade.status());
+                println!("REVERSE {frames} ({} more)", history.len());
+                continue;
+            },
+        };
+        count = 0;
 
-    // set free play mode
-    cpu.set_memory(0, 2);
+        let frame = history.step(&mut arcade, joystick);
+        session.record(&arcade, joystick);
+        renderer.render(arcade.screen(), &frame);
 
-    while !cpu.is_terminated() {
-        cpu.run();
+        // you can never lose. this will pop back to before you lost
+        if arcade.screen().will_end() || frame.events.contains(&arcade::GameEvent::GameOver) {
+            history.rewind(&mut arcade, 1);
+            session.truncate(arcade.frame());
+            renderer.render(arcade.screen(), &arcade.status());
+            println!("RESCUE");
+        }
+    }
 
-        if cpu.output_len() > 1 {
-            cpu.output()
-                .chunks(3)
-                .for_each(|p| screen.set(p[0], p[1], p[2]));
+    drop(keyboard);
 
-            screen.show();
-        }
+    if arcade.is_over() {
+        session.finish(&arcade);
+    }
+    session.save("session.txt").expect("failed to save session");
+    println!("session saved to session.txt ({} frames)", session.frames());
 
-        let k = keys.pop().unwrap();
-        println!("send {k}");
-        cpu.push_input(k);
-        std::thread::sleep(std::time::Duration::from_millis(50));
+    if let Some(journal) = arcade.cpu().take_journal() {
+        journal.save("journal.txt").expect("failed to save journal");
     }
 
-    return screen.get(-1, 0) as usize;
+    return arcade.screen().score() as usize;
 }
 
-// interactive version with "undo" that records and prints out input
-// a = left
-// d = right
-// s or return = nothing
-// w = reWind
-// q = quit
-// play this version until you win, then copy-pate the output to keys.txt
-// to use playback()
-#[allow(dead_code)]
-fn interactive(program: &Vec<i64>) -> usize {
-    let mut screen = Screen::create();
-    let mut cpu = intcode::CPU::load_program(program);
-
-    let mut save: Vec<(intcode::CPU, Screen, Vec<char>)> = Vec::new();
-    let mut keys = Vec::new();
-
-    // set free play mode
-    cpu.set_memory(0, 2);
-
-    while !cpu.is_terminated() {
-        // limit save states to 100, do it for the memory!
-        while save.len() > 100 {
-            save.remove(0);
-        }
-        // save current state of CPU, screen and keylogger
-        save.push((cpu.clone(), screen.clone(), keys.clone()));
+// play back a saved session, checking it still reaches the recorded
+// score. With a frame only that frame is shown
+fn playback(program: &Vec<i64>, session: &Session, seek: Option<usize>, renderer: &mut dyn Renderer) -> usize {
+    let score = match seek {
+        Some(frame) => session.seek(program, frame).map(|arcade| {
+            renderer.render(arcade.screen(), &arcade.status());
+            arcade.screen().score()
+        }),
+        None => session.verify(program, renderer),
+    };
+
+    return score.unwrap_or_else(|error| {
+        println!("ERROR: replay failed, {error}");
+        process::exit(1);
+    }) as usize;
+}
 
+// headless version that plays itself by moving the paddle under the ball,
+// or with predict to where the ball is going to come down
+fn autopilot(program: &Vec<i64>, predict: bool, renderer: &mut dyn Renderer) -> usize {
+    let mut arcade = load(program, true);
+    arcade.set_predicting(predict);
+    let mut joystick = None;
+
+    while !arcade.is_over() {
+        let frame = arcade.step(joystick);
+        renderer.render(arcade.screen(), &frame);
+
+        // joystick -1, 0, 1 towards the ball
+        let target = frame.landing.map(|landing| landing.x).or(frame.ball.map(|(x, _)| x));
+        joystick = match (target, frame.paddle) {
+            (Some(target), Some((paddle, _))) => Some((target - paddle).signum()),
+            _ => Some(0),
+        };
+    }
 
-        cpu.run();
+    return arcade.screen().score() as usize;
+}
 
-        // if output, update screen
-        if cpu.output_len() > 1 {
-            cpu.output()
-                .chunks(3)
-                .for_each(|p| screen.set(p[0], p[1], p[2]));
+// command line options that follow the input file
+struct Options {
+    renderer: Box<dyn Renderer>,    // --render none|ansi|text|png:PREFIX|ppm:PREFIX|gif:FILE
+    play: bool,                     // --play, part 2 by hand
+    predict: bool,                  // --predict, show or aim for where the ball lands
+    tick: Option<Duration>,         // --tick MS, neutral if no key in time
+    resume: Option<Session>,        // --resume FILE, play on from a session
+    replay: Option<Session>,        // --replay FILE, play a session back
+    seek: Option<usize>,            // --seek FRAME, only show this frame
+    journal: Option<String>,        // --journal FILE, replay recorded I/O
+}
 
-            screen.show();
-        }
+// pixels per tile in exported images
+const DEFAULT_SCALE: usize = 4;
 
-        // you can never lose. this will pop back to before you lost
-        if screen.will_end() {
-            save.pop();
-            (cpu, screen, keys) = save.pop().unwrap();
-            screen.show();
-            println!("RESCUE");
-            continue;
-        }
+fn read_session(filename: Option<&String>) -> Result<Session, String> {
+    let filename = filename.ok_or("need a session file")?;
+    return Session::load(filename);
+}
 
-        // get input -- with rust you need to hit key and then return
-        let mut line = String::new();
-        std::io::stdin().read_line(&mut line).expect("failed to read");
-
-        keys.push(line.chars().nth(0).unwrap());
-        match line.chars().nth(0).unwrap() {
-            'q' => break,                       // quit
-            'a' => cpu.push_input(-1),          // left
-            's' => cpu.push_input(0),           // nothing
-            'd' => cpu.push_input(1),           // right
-            'w' => {                            // rewind
-                save.pop();
-                (cpu, screen, keys) = save.pop().unwrap();
-                screen.show();
-                println!("REVERSE");
+fn read_options(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        renderer: Box::new(arcade::NoRenderer), play: false, predict: false, tick: None,
+        resume: None, replay: None, seek: None, journal: None,
+    };
+    let mut args = args.iter();
+
+    // the renderer is built last so --palette and --scale can come after it
+    let mut render = "none";
+    let mut palette = arcade::palette();
+    let mut scale = DEFAULT_SCALE;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--play" => options.play = true,
+            "--predict" => options.predict = true,
+            "--render" => render = args.next().ok_or("--render needs one of none, ansi, text, png:PREFIX, ppm:PREFIX, gif:FILE")?,
+            "--palette" => palette.parse_colours(args.next().ok_or("--palette needs TILE=RRGGBB,...")?)?,
+            "--scale" => {
+                let pixels = args.next().ok_or("--scale needs pixels per tile")?;
+                scale = pixels.parse().ok().filter(|&n| n > 0).ok_or(format!("Bad scale {pixels}"))?;
             },
-            _ => cpu.push_input(0),             // nothing
-        };
+            "--tick" => {
+                let ms = args.next().ok_or("--tick needs milliseconds")?;
+                let ms = ms.parse().map_err(|_| format!("Bad tick {ms}"))?;
+                options.tick = Some(Duration::from_millis(ms));
+            },
+            "--resume" => {
+                options.resume = Some(read_session(args.next())?);
+                options.play = true;
+            },
+            "--replay" => options.replay = Some(read_session(args.next())?),
+            "--journal" => options.journal = Some(args.next().ok_or("--journal needs a journal file")?.clone()),
+            "--seek" => {
+                let frame = args.next().ok_or("--seek needs a frame")?;
+                options.seek = Some(frame.parse().map_err(|_| format!("Bad frame {frame}"))?);
+            },
+            _ => return Err(format!("Unknown option {arg}")),
+        }
     }
 
-    // print out keys we hit for replay
-    let keymap: Vec<_> = keys.iter()
-        .map(|k| match k {
-            'a' => -1,
-            'd' => 1,
-            's' => 0,
-            '\n' => 0,
-            _ => 2
-        })
-        .collect();
-
-    println!("{keymap:?}");
-    return screen.get(-1, 0) as usize;
+    options.renderer = arcade::renderer(render, &palette, scale).ok_or(format!("Unknown renderer {render}"))?;
+    return Ok(options);
 }
 
-fn part2(program: &Vec<i64>) -> usize {
-    return interactive(program);
-    // return playback(program, "keys.txt");
+fn part2(program: &Vec<i64>, options: &mut Options) -> usize {
+    if options.play {
+        return interactive(program, options.tick, options.predict, options.resume.take());
+    }
+
+    let score = match (&options.replay, &options.journal) {
+        (Some(session), _) => playback(program, session, options.seek, options.renderer.as_mut()),
+        (None, Some(journal)) => replay(program, journal, options.renderer.as_mut()),
+        (None, None) => autopilot(program, options.predict, options.renderer.as_mut()),
+    };
+
+    options.renderer.finish();
+    return score;
 }
 
 
@@ -227,6 +280,11 @@ fn main() {
         process::exit(1);
     }
 
+    let mut options = read_options(&args[2..]).unwrap_or_else(|error| {
+        println!("ERROR: {error}");
+        process::exit(1);
+    });
+
     let start = Instant::now();
 
     let data = read_data(&args[1]);
@@ -243,7 +301,7 @@ fn main() {
     );
 
     let p2_start = Instant::now();
-    let p2 = part2(&data);
+    let p2 = part2(&data, &mut options);
 
     println!(
         "{p2:>15} ({:9.9} ns)",
@@ -254,3 +312,30 @@ fn main() {
         Instant::now().duration_since(start).as_nanos()
     );
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the paddle starts at x 0 under a ball that stays at x 4, the joystick
+    // moves it. Reaching the ball scores 1000 and ends the game, after 20
+    // frames it gives up with 0
+    pub const CATCH: [i64; 54] = [
+        1, 0, 0, 100,
+        4, 101, 104, 5, 104, 3,         // paddle (x, 5)
+        104, 4, 104, 2, 104, 4,         // ball (4, 2)
+        3, 102, 1, 101, 102, 101,       // x += joystick
+        1008, 101, 4, 103, 1005, 103, 47,
+        1001, 104, 1, 104, 1007, 104, 20, 105, 1005, 105, 4,
+        104, -1, 104, 0, 104, 0, 99,
+        104, -1, 104, 0, 104, 1000, 99,
+    ];
+
+    #[test]
+    fn autopilot_catches_the_ball() {
+        let program = CATCH.to_vec();
+
+        assert_eq!(autopilot(&program, false, &mut arcade::NoRenderer), 1000);
+        assert_eq!(autopilot(&program, true, &mut arcade::NoRenderer), 1000);
+    }
+}
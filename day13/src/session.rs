@@ -0,0 +1,344 @@
+use std::fs;
+use std::io;
+use std::fmt::Write;
+use crate::intcode;
+use crate::arcade::{self, Arcade, Renderer, Snapshot};
+
+// A recorded game that can be shared, resumed, seeked and verified
+//
+// Saved as text, one record per line:
+//
+//   program 8c1b2a3f00e1d2c4       FNV-1a hash of the program it was played on
+//   patch 0 2                      memory patches applied before booting
+//   every 100                      snapshot interval in frames (0 = none)
+//   score 14                       final score, if the game was finished
+//   keys -1,0,1,1,...              joystick input, one per frame after boot
+//   snapshot 101 120 1064 52311    frame, pc, relative base, cycles
+//   memory 1,380,379,...           CPU memory for the snapshot above
+//   screen 0,0,1;1,0,1;...         tiles for the snapshot above (x,y,value)
+//
+// A bare comma separated list of keys (the old keys.txt) is also accepted,
+// it is played in free play on whatever program is given.
+//
+// Frame 1 is the boot frame, frame n has used keys[0..n-1].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Session {
+    pub program: Option<u64>,
+    pub patches: Vec<(usize, i64)>,
+    pub every: usize,
+    pub score: Option<i64>,
+    pub keys: Vec<i64>,
+    pub snapshots: Vec<Snapshot>,
+}
+
+// FNV-1a over the little endian bytes of the program
+pub fn hash(program: &[i64]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in program.iter().flat_map(|value| value.to_le_bytes()) {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+
+    return hash;
+}
+
+fn parse<T: std::str::FromStr>(text: &str, line: usize) -> Result<T, String> {
+    return text.trim().parse().map_err(|_| format!("line {line}: bad number {text}"));
+}
+
+fn parse_list(text: &str, line: usize) -> Result<Vec<i64>, String> {
+    return text.split(',')
+        .filter(|value| !value.trim().is_empty())
+        .map(|value| parse(value, line))
+        .collect();
+}
+
+// ((x, y), value) as in a snapshot
+type Tile = ((i64, i64), i64);
+
+fn parse_tiles(text: &str, line: usize) -> Result<Vec<Tile>, String> {
+    return text.split(';')
+        .filter(|tile| !tile.trim().is_empty())
+        .map(|tile| {
+            match parse_list(tile, line)?[..] {
+                [x, y, value] => Ok(((x, y), value)),
+                _ => Err(format!("line {line}: bad tile {tile}")),
+            }
+        })
+        .collect();
+}
+
+fn join(values: &[i64]) -> String {
+    return values.iter().map(|value| value.to_string()).collect::<Vec<_>>().join(",");
+}
+
+impl Session {
+    pub fn new(program: &[i64], patches: Vec<(usize, i64)>, every: usize) -> Self {
+        return Session {
+            program: Some(hash(program)),
+            patches,
+            every,
+            score: None,
+            keys: Vec::new(),
+            snapshots: Vec::new(),
+        };
+    }
+
+    pub fn load(filename: &str) -> Result<Session, String> {
+        let text = fs::read_to_string(filename).map_err(|error| format!("{filename}: {error}"))?;
+
+        // old keys.txt, just the keys
+        if text.trim_start().starts_with(|c: char| c == '-' || c.is_ascii_digit()) {
+            return Ok(Session {
+                program: None,
+                patches: vec![arcade::FREE_PLAY],
+                every: 0,
+                score: None,
+                keys: parse_list(&text, 1)?,
+                snapshots: Vec::new(),
+            });
+        }
+
+        let mut session = Session {
+            program: None, patches: Vec::new(), every: 0, score: None,
+            keys: Vec::new(), snapshots: Vec::new(),
+        };
+
+        for (i, line) in text.lines().enumerate() {
+            let line_number = i + 1;
+            let (record, rest) = line.split_once(' ').unwrap_or((line, ""));
+            let fields: Vec<&str> = rest.split_whitespace().collect();
+
+            match (record, &fields[..]) {
+                ("", []) => {},
+                ("program", [hash]) => {
+                    let hash = u64::from_str_radix(hash, 16)
+                        .map_err(|_| format!("line {line_number}: bad hash {hash}"))?;
+                    session.program = Some(hash);
+                },
+                ("patch", [address, value]) => {
+                    session.patches.push((parse(address, line_number)?, parse(value, line_number)?));
+                },
+                ("every", [every]) => session.every = parse(every, line_number)?,
+                ("score", [score]) => session.score = Some(parse(score, line_number)?),
+                ("keys", _) => session.keys.extend(parse_list(rest, line_number)?),
+                ("snapshot", [frame, pc, base, cycles]) => {
+                    session.snapshots.push(Snapshot {
+                        frame: parse(frame, line_number)?,
+                        cpu: intcode::CpuState {
+                            pc: parse(pc, line_number)?,
+                            base: parse(base, line_number)?,
+                            cycles: parse(cycles, line_number)?,
+                            memory: Vec::new(),
+                        },
+                        tiles: Vec::new(),
+                    });
+                },
+                ("memory", _) | ("screen", _) => {
+                    let snapshot = session.snapshots.last_mut()
+                        .ok_or(format!("line {line_number}: {record} before any snapshot"))?;
+                    if record == "memory" {
+                        snapshot.cpu.memory = parse_list(rest, line_number)?;
+                    } else {
+                        snapshot.tiles = parse_tiles(rest, line_number)?;
+                    }
+                },
+                _ => return Err(format!("line {line_number}: can't read {line}")),
+            }
+        }
+
+        return Ok(session);
+    }
+
+    pub fn save(&self, filename: &str) -> io::Result<()> {
+        let mut text = String::new();
+
+        if let Some(hash) = self.program {
+            writeln!(text, "program {hash:016x}").expect("failed");
+        }
+        for (address, value) in &self.patches {
+            writeln!(text, "patch {address} {value}").expect("failed");
+        }
+        writeln!(text, "every {}", self.every).expect("failed");
+        if let Some(score) = self.score {
+            writeln!(text, "score {score}").expect("failed");
+        }
+        writeln!(text, "keys {}", join(&self.keys)).expect("failed");
+
+        for snapshot in &self.snapshots {
+            let cpu = &snapshot.cpu;
+            writeln!(text, "snapshot {} {} {} {}", snapshot.frame, cpu.pc, cpu.base, cpu.cycles).expect("failed");
+            writeln!(text, "memory {}", join(&cpu.memory)).expect("failed");
+
+            let tiles: Vec<String> = snapshot.tiles.iter()
+                .map(|((x, y), value)| format!("{x},{y},{value}"))
+                .collect();
+            writeln!(text, "screen {}", tiles.join(";")).expect("failed");
+        }
+
+        return fs::write(filename, text);
+    }
+
+    // last frame the session reaches
+    pub fn frames(&self) -> usize {
+        return self.keys.len() + 1;
+    }
+
+    fn check(&self, program: &[i64]) -> Result<(), String> {
+        return match self.program {
+            Some(expected) if expected != hash(program) => {
+                Err(format!("session was recorded on program {expected:016x}, not {:016x}", hash(program)))
+            },
+            _ => Ok(()),
+        };
+    }
+
+    // the game at frame 0, nothing run yet
+    pub fn start(&self, program: &Vec<i64>) -> Result<Arcade, String> {
+        self.check(program)?;
+        return Arcade::patched(program, &self.patches).map_err(|error| format!("bad patch, {error}"));
+    }
+
+    // the game at a frame (capped to the end of the session), starting from
+    // the closest snapshot before it
+    pub fn seek(&self, program: &Vec<i64>, frame: usize) -> Result<Arcade, String> {
+        let frame = frame.min(self.frames());
+
+        let mut arcade = match self.snapshots.iter().rev().find(|snapshot| snapshot.frame <= frame) {
+            Some(snapshot) => {
+                self.check(program)?;
+                Arcade::restore(program, snapshot)
+            },
+            None => self.start(program)?,
+        };
+
+        while arcade.frame() < frame && !arcade.is_over() {
+            let joystick = arcade.frame().checked_sub(1).map(|i| self.keys[i]);
+            arcade.step(joystick);
+        }
+
+        return Ok(arcade);
+    }
+
+    // play the whole session from the start, checking every snapshot and
+    // the final score match what was recorded
+    pub fn verify(&self, program: &Vec<i64>, renderer: &mut dyn Renderer) -> Result<i64, String> {
+        let mut arcade = self.start(program)?;
+        let mut snapshots = self.snapshots.iter().peekable();
+
+        while arcade.frame() < self.frames() && !arcade.is_over() {
+            let joystick = arcade.frame().checked_sub(1).map(|i| self.keys[i]);
+            let frame = arcade.step(joystick);
+            renderer.render(arcade.screen(), &frame);
+
+            while let Some(snapshot) = snapshots.next_if(|snapshot| snapshot.frame <= frame.number) {
+                // compare through a restored game so how the snapshot lists
+                // its tiles doesn't matter
+                if snapshot.frame == frame.number && Arcade::restore(program, snapshot).snapshot() != arcade.snapshot() {
+                    return Err(format!("frame {} differs from its snapshot", frame.number));
+                }
+            }
+        }
+
+        if arcade.frame() < self.frames() {
+            return Err(format!("game ended at frame {} with {} keys left", arcade.frame(), self.frames() - arcade.frame()));
+        }
+
+        let score = arcade.screen().score();
+        return match self.score {
+            Some(expected) if expected != score => Err(format!("final score {score}, recorded {expected}")),
+            _ => Ok(score),
+        };
+    }
+
+    // add a frame the game has just run with joystick
+    pub fn record(&mut self, arcade: &Arcade, joystick: i64) {
+        self.keys.push(joystick);
+
+        if self.every > 0 && arcade.frame().is_multiple_of(self.every) {
+            self.snapshots.push(arcade.snapshot());
+        }
+    }
+
+    // forget everything after frame
+    pub fn truncate(&mut self, frame: usize) {
+        self.keys.truncate(frame.saturating_sub(1));
+        self.snapshots.retain(|snapshot| snapshot.frame <= frame);
+        self.score = None;
+    }
+
+    pub fn finish(&mut self, arcade: &Arcade) {
+        self.score = Some(arcade.screen().score());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::CATCH;
+
+    // the paddle steered right all the way to the ball
+    fn play(program: &Vec<i64>) -> Session {
+        let mut session = Session::new(program, vec![arcade::FREE_PLAY], 2);
+        let mut arcade = session.start(program).unwrap();
+        arcade.step(None);
+        while !arcade.is_over() {
+            arcade.step(Some(1));
+            session.record(&arcade, 1);
+        }
+        session.finish(&arcade);
+        return session;
+    }
+
+    #[test]
+    fn saves_loads_seeks_and_verifies() {
+        let program = CATCH.to_vec();
+        let session = play(&program);
+        assert_eq!((session.keys.len(), session.score), (4, Some(1000)));
+        assert_eq!(session.snapshots.iter().map(|snapshot| snapshot.frame).collect::<Vec<_>>(), vec![2, 4]);
+
+        let filename = std::env::temp_dir().join(format!("session-{}.txt", std::process::id()));
+        let filename = filename.to_str().unwrap();
+        session.save(filename).unwrap();
+        let text = fs::read_to_string(filename).unwrap();
+        let loaded = Session::load(filename);
+
+        // a byte changed in the snapshot memory, after the joystick cell
+        let (memory, _rest) = text.split_once("\nmemory ").unwrap();
+        let mut changed = text.clone().into_bytes();
+        let at = memory.len() + "\nmemory 2,0,".len();
+        changed[at] = if changed[at] == b'9' { b'8' } else { b'9' };
+        fs::write(filename, &changed).unwrap();
+        let tampered = Session::load(filename);
+        fs::remove_file(filename).unwrap();
+
+        let loaded = loaded.unwrap();
+        assert_eq!(loaded, session);
+        assert_eq!(loaded.verify(&program, &mut arcade::NoRenderer), Ok(1000));
+
+        // seeking from the snapshot at 2 lands where playing does
+        let mut played = loaded.start(&program).unwrap();
+        played.step(None);
+        played.step(Some(1));
+        played.step(Some(1));
+        let seeked = loaded.seek(&program, 3).unwrap();
+        assert_eq!((seeked.frame(), seeked.snapshot()), (3, played.snapshot()));
+
+        let tampered = tampered.unwrap();
+        assert_ne!(tampered, session);
+        assert_eq!(tampered.verify(&program, &mut arcade::NoRenderer), Err("frame 2 differs from its snapshot".to_string()));
+    }
+
+    #[test]
+    fn verify_catches_other_games() {
+        let program = CATCH.to_vec();
+        let mut session = play(&program);
+
+        let mut other = program.clone();
+        other[52] = 999;
+        assert!(session.verify(&other, &mut arcade::NoRenderer).unwrap_err().starts_with("session was recorded on program"));
+
+        session.score = Some(999);
+        assert_eq!(session.verify(&program, &mut arcade::NoRenderer), Err("final score 1000, recorded 999".to_string()));
+    }
+}
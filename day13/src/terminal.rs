@@ -0,0 +1,150 @@
+use std::io;
+use std::io::Read;
+use std::fs::File;
+use std::panic;
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+// Keys the arcade understands
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Key {
+    Left,       // a or left arrow
+    Right,      // d or right arrow
+    Neutral,    // s, space, enter or down arrow
+    Rewind,     // w or up arrow
+    Digit(u8),  // 0-9, a count for rewind
+    Quit,       // q or ctrl-c
+}
+
+// run stty against the controlling terminal, returns what it printed
+fn stty(args: &[&str]) -> io::Result<String> {
+    let tty = File::open("/dev/tty")?;
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(Stdio::from(tty))
+        .stderr(Stdio::null())
+        .output()?;
+
+    if !output.status.success() {
+        return Err(io::Error::other("stty failed"));
+    }
+
+    return Ok(String::from_utf8_lossy(&output.stdout).trim().to_string());
+}
+
+type PanicHook = Box<dyn Fn(&panic::PanicHookInfo<'_>) + Sync + Send + 'static>;
+
+// Puts the terminal in raw (unbuffered, no echo) mode and puts it back the
+// way it was when dropped or when the program panics
+pub struct RawMode {
+    saved: String,
+    previous: Option<Arc<PanicHook>>,  // panic hook from before raw mode
+}
+
+impl RawMode {
+    pub fn enable() -> io::Result<Self> {
+        let saved = stty(&["-g"])?;
+
+        // keep output processing so "\n" still returns the carriage, but
+        // deliver every key press (ctrl-c included) straight away
+        stty(&["-icanon", "-echo", "-isig", "min", "1", "time", "0"])?;
+
+        // a panic unwinds through drop() as well, but restore before the
+        // panic message is printed so it is readable
+        let restore = saved.clone();
+        let previous = Arc::new(panic::take_hook());
+        let hook = Arc::clone(&previous);
+        panic::set_hook(Box::new(move |info| {
+            let _ = stty(&[&restore]);
+            hook(info);
+        }));
+
+        print!("\x1b[?25l");    // hide cursor
+        return Ok(RawMode { saved, previous: Some(previous) });
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        print!("\x1b[?25h");    // show cursor
+        let _ = stty(&[&self.saved]);
+
+        // back to the panic hook from before (not allowed while panicking).
+        // Dropping ours leaves this the only handle on it
+        if !thread::panicking() {
+            drop(panic::take_hook());
+            if let Some(previous) = self.previous.take() {
+                match Arc::try_unwrap(previous) {
+                    Ok(hook) => panic::set_hook(hook),
+                    Err(shared) => panic::set_hook(Box::new(move |info| shared(info))),
+                }
+            }
+        }
+    }
+}
+
+// turn the bytes of one key press into a Key
+fn decode(byte: u8, bytes: &mut impl Iterator<Item = u8>) -> Option<Key> {
+    return match byte {
+        b'a' | b'A' => Some(Key::Left),
+        b'd' | b'D' => Some(Key::Right),
+        b's' | b'S' | b' ' | b'\n' | b'\r' => Some(Key::Neutral),
+        b'w' | b'W' => Some(Key::Rewind),
+        b'q' | b'Q' | 3 => Some(Key::Quit),
+        b'0'..=b'9' => Some(Key::Digit(byte - b'0')),
+        27 => {
+            // ESC [ A/B/C/D arrow keys
+            if bytes.next() != Some(b'[') {
+                return None;
+            }
+            match bytes.next() {
+                Some(b'A') => Some(Key::Rewind),
+                Some(b'B') => Some(Key::Neutral),
+                Some(b'C') => Some(Key::Right),
+                Some(b'D') => Some(Key::Left),
+                _ => None,
+            }
+        },
+        _ => None,
+    };
+}
+
+// Single key presses from the terminal, read on a background thread
+pub struct Keyboard {
+    keys: Receiver<Key>,
+    _raw: RawMode,
+}
+
+impl Keyboard {
+    pub fn open() -> io::Result<Self> {
+        let raw = RawMode::enable()?;
+        let (sender, keys) = mpsc::channel();
+
+        thread::spawn(move || {
+            let mut bytes = io::stdin().lock().bytes().filter_map(|b| b.ok());
+            while let Some(byte) = bytes.next() {
+                if let Some(key) = decode(byte, &mut bytes) && sender.send(key).is_err() {
+                    break;
+                }
+            }
+        });
+
+        return Ok(Keyboard { keys, _raw: raw });
+    }
+
+    // wait for the next key. With a tick, Neutral is returned if nothing
+    // was pressed in time so the game keeps moving
+    pub fn wait(&self, tick: Option<Duration>) -> Key {
+        return match tick {
+            Some(tick) => match self.keys.recv_timeout(tick) {
+                Ok(key) => key,
+                Err(RecvTimeoutError::Timeout) => Key::Neutral,
+                Err(RecvTimeoutError::Disconnected) => Key::Quit,
+            },
+            None => self.keys.recv().unwrap_or(Key::Quit),
+        };
+    }
+}